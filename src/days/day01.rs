@@ -1,7 +1,46 @@
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
 use std::collections::hash_map;
 
-pub fn parse_input(input: &str) -> &str {
-    input
+// Part 2 looks for spelled out digits by byte offset, so only ASCII will do.
+pub fn parse_input(input: &str) -> Result<&str, ParseError> {
+    match numbered_lines(input).find(|(_, line)| !line.is_ascii()) {
+        Some((n, _)) => Err(ParseError::new(n, "expected ASCII text")),
+        None => Ok(input),
+    }
+}
+
+// Lines of letters with digits mixed in, some of them spelled out. Every line
+// gets at least one real digit so part 1 always has one to find.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    const WORDS: [&str; 9] = [
+        "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    ];
+    let digit = |rng: &mut Rng| char::from(b'0' + rng.range(1..10) as u8);
+
+    let mut out = String::new();
+    for _ in 0..size {
+        let mut line = String::new();
+        for _ in 0..rng.range(1..6) {
+            match rng.below(3) {
+                0 => line.push(digit(rng)),
+                1 => line.push_str(WORDS[rng.below(WORDS.len())]),
+                _ => {
+                    for _ in 0..rng.range(1..6) {
+                        line.push(char::from(b'a' + rng.below(26) as u8));
+                    }
+                }
+            }
+        }
+        let pos = rng.below(line.len() + 1);
+        line.insert(pos, digit(rng));
+        out.push_str(&line);
+        out.push('\n');
+    }
+
+    out
 }
 
 #[allow(unused_variables)]
@@ -69,13 +108,19 @@ pub fn part2(input: &str) -> Option<u64> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day01/test.txt");
     #[test]
     fn test_day1_part_one() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(input);
 
         assert_eq!(resp, None);
     }
+    #[test]
+    fn test_day1_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
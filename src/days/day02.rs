@@ -1,9 +1,14 @@
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{alpha1, digit1, newline, none_of},
+    character::complete::{digit1, none_of},
+    combinator::{all_consuming, map_res},
     multi::{many1, separated_list1},
-    sequence::{preceded, separated_pair, terminated, tuple},
+    sequence::{preceded, separated_pair},
     IResult,
 };
 
@@ -20,11 +25,11 @@ struct CubeSet {
 }
 
 fn parse_pair(input: &str) -> IResult<&str, (u64, &str)> {
-    let (input, (n, color)) = separated_pair(digit1, tag(" "), alpha1)(input)?;
-
-    let n = n.parse().unwrap();
-
-    Ok((input, (n, color)))
+    separated_pair(
+        map_res(digit1, str::parse),
+        tag(" "),
+        alt((tag("red"), tag("green"), tag("blue"))),
+    )(input)
 }
 
 fn parse_set(input: &str) -> IResult<&str, CubeSet> {
@@ -59,10 +64,34 @@ fn parse_line(input: &str) -> IResult<&str, Game> {
     Ok((input, game))
 }
 
-pub fn parse_input(input: &str) -> Vec<Game> {
-    let (_, games) = separated_list1(newline, parse_line)(input).unwrap();
+pub fn parse_input(input: &str) -> Result<Vec<Game>, ParseError> {
+    numbered_lines(input)
+        .map(|(n, line)| match all_consuming(parse_line)(line) {
+            Ok((_, game)) => Ok(game),
+            Err(_) => Err(ParseError::new(n, "expected a game")),
+        })
+        .collect()
+}
+
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let mut out = String::new();
+    for i in 1..=size {
+        let sets: Vec<String> = (0..rng.range(1..7))
+            .map(|_| {
+                let mut colours = ["red", "green", "blue"];
+                rng.shuffle(&mut colours);
+                let count = rng.range(1..4) as usize;
+                colours[..count]
+                    .iter()
+                    .map(|colour| format!("{} {}", rng.range(1..21), colour))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect();
+        out.push_str(&format!("Game {}: {}\n", i, sets.join("; ")));
+    }
 
-    games
+    out
 }
 
 pub fn part1(input: &[Game]) -> Option<u64> {
@@ -102,3 +131,16 @@ pub fn part2(input: &[Game]) -> Option<u64> {
 
     Some(sum)
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::utils::fuzz::assert_parser_total;
+    const TEST_INPUT: &str = include_str!("../../input/day02/test.txt");
+
+    #[test]
+    fn test_day02_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
+}
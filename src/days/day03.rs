@@ -1,3 +1,7 @@
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
 use std::collections::HashMap;
 
 type Point = (i32, i32);
@@ -8,9 +12,10 @@ pub enum Item {
     Symbol(char),
 }
 
-pub fn parse_input(input: &str) -> HashMap<Point, Item> {
+pub fn parse_input(input: &str) -> Result<HashMap<Point, Item>, ParseError> {
     let mut map: HashMap<Point, Item> = HashMap::new();
-    for (i, line) in input.lines().enumerate() {
+    for (n, line) in numbered_lines(input) {
+        let i = n - 1;
         let bytes = line.as_bytes();
         let width = line.len();
         let mut j = 0;
@@ -19,10 +24,13 @@ pub fn parse_input(input: &str) -> HashMap<Point, Item> {
             match c {
                 '0'..='9' => {
                     let start = j;
-                    let mut num = 0;
+                    let mut num: u64 = 0;
                     let mut count = 0;
                     while c.is_ascii_digit() {
-                        num = num * 10 + c as u64 - '0' as u64;
+                        num = num
+                            .checked_mul(10)
+                            .and_then(|num| num.checked_add(c as u64 - '0' as u64))
+                            .ok_or(ParseError::new(n, "number too large"))?;
                         count += 1;
                         j += 1;
                         if j >= width {
@@ -49,7 +57,36 @@ pub fn parse_input(input: &str) -> HashMap<Point, Item> {
         }
     }
 
-    map
+    Ok(map)
+}
+
+// A square schematic with numbers of up to three digits and symbols scattered
+// over it. Numbers are always followed by a dot so they never run together.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    const SYMBOLS: &[u8] = b"**#+$/@=%&-";
+
+    let mut out = String::new();
+    for _ in 0..size {
+        let mut line = Vec::new();
+        while line.len() < size {
+            match rng.below(10) {
+                0 | 1 => {
+                    line.push(b'0' + rng.range(1..10) as u8);
+                    for _ in 0..rng.below(3) {
+                        line.push(b'0' + rng.below(10) as u8);
+                    }
+                    line.push(b'.');
+                }
+                2 => line.push(*rng.choose(SYMBOLS)),
+                _ => line.push(b'.'),
+            }
+        }
+        line.truncate(size);
+        out.push_str(&String::from_utf8(line).unwrap());
+        out.push('\n');
+    }
+
+    out
 }
 
 #[allow(unused_variables)]
@@ -121,13 +158,20 @@ pub fn part2(input: &HashMap<Point, Item>) -> Option<u64> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day03/test.txt");
     #[test]
     fn test_day0_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
         assert_eq!(resp, Some(4361));
     }
+
+    #[test]
+    fn test_day0_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
@@ -1,10 +1,16 @@
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
 use std::collections::{HashMap, HashSet};
 
-pub fn parse_input(input: &str) -> Vec<i32> {
+pub fn parse_input(input: &str) -> Result<Vec<i32>, ParseError> {
     let mut output = Vec::new();
-    for line in input.lines() {
+    for (n, line) in numbered_lines(input) {
         let it = line.chars();
-        let skip = line.find(": ").unwrap();
+        let skip = line
+            .find(": ")
+            .ok_or(ParseError::new(n, "expected a card number"))?;
         let mut it = it.skip(skip + 1);
 
         let mut flag = true;
@@ -16,7 +22,10 @@ pub fn parse_input(input: &str) -> Vec<i32> {
                     let mut v = c as u64 - '0' as u64;
                     for c in it.by_ref() {
                         if c.is_ascii_digit() {
-                            v = v * 10 + c as u64 - '0' as u64;
+                            v = v
+                                .checked_mul(10)
+                                .and_then(|v| v.checked_add(c as u64 - '0' as u64))
+                                .ok_or(ParseError::new(n, "number too large"))?;
                         } else {
                             break;
                         }
@@ -36,7 +45,55 @@ pub fn parse_input(input: &str) -> Vec<i32> {
         output.push(count);
     }
 
-    output
+    Ok(output)
+}
+
+// Cards with 10 winning numbers and 25 numbers you have. No card wins copies
+// of cards past the end of the table, and wins are dropped once they'd push
+// the copy counts of part 2 anywhere near overflowing.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    const LIMIT: u64 = 1 << 40;
+
+    let mut copies = vec![1u64; size];
+    let mut out = String::new();
+    for i in 0..size {
+        let mut matches = rng.below(11).min(size - i - 1);
+        if copies[i + 1..=i + matches]
+            .iter()
+            .any(|&c| c + copies[i] > LIMIT)
+        {
+            matches = 0;
+        }
+        let won = copies[i];
+        for c in &mut copies[i + 1..=i + matches] {
+            *c += won;
+        }
+
+        let mut numbers: Vec<u64> = (1..100).collect();
+        rng.shuffle(&mut numbers);
+        let winners = &numbers[..10];
+        let mut have: Vec<u64> = winners[..matches]
+            .iter()
+            .chain(&numbers[10..35 - matches])
+            .copied()
+            .collect();
+        rng.shuffle(&mut have);
+
+        let format = |nums: &[u64]| {
+            nums.iter()
+                .map(|n| format!("{:>2}", n))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        out.push_str(&format!(
+            "Card {:>3}: {} | {}\n",
+            i + 1,
+            format(winners),
+            format(&have)
+        ));
+    }
+
+    out
 }
 
 #[allow(unused_variables)]
@@ -64,10 +121,11 @@ pub fn part2(input: &[i32]) -> Option<u64> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day04/test.txt");
     #[test]
     fn test_day4_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -76,10 +134,16 @@ pub mod tests {
 
     #[test]
     fn test_day4_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(30));
     }
+
+    #[test]
+    fn test_day4_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
@@ -1,4 +1,10 @@
-#[derive(Debug)]
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    rng::Rng,
+};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Range {
     start: u64,
     end: u64,
@@ -10,74 +16,218 @@ pub struct MapRange {
     length: u64,
 }
 
-pub struct Input {
-    seeds: Vec<u64>,
-    maps: Vec<Vec<MapRange>>,
+// Maps start..end to x + offset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Piece {
+    start: u64,
+    end: u64,
+    offset: i64,
 }
 
-// Assume the map ranges are sorted
-fn map_range(src_range: &Range, map_ranges: &[MapRange]) -> Vec<Range> {
-    let mut curr = src_range.start;
-    let end = src_range.end;
-    let mut out_ranges = Vec::new();
+// A piecewise-linear map over the whole of 0..u64::MAX. Pieces are sorted
+// and touch end to end, gaps between map lines being filled with identity
+// pieces.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mapping {
+    pieces: Vec<Piece>,
+}
 
-    let mut map_it = map_ranges.iter();
-    let mut map = map_it.next();
-    while curr < end {
-        // MapRange is beyond our source range, no more mapping needed
-        if map.is_none() || end < map.unwrap().src_start {
-            out_ranges.push(Range { start: curr, end });
-            break;
-        }
-        let map_range = map.unwrap();
+fn shift(x: u64, offset: i64) -> u64 {
+    x.wrapping_add_signed(offset)
+}
 
-        // Our current pointer is beyond this map, iterate the map
-        if curr > map_range.src_start + map_range.length {
-            map = map_it.next();
-            continue;
+impl Mapping {
+    pub fn identity() -> Mapping {
+        Mapping {
+            pieces: vec![Piece {
+                start: 0,
+                end: u64::MAX,
+                offset: 0,
+            }],
         }
+    }
 
-        if curr < map_range.src_start {
-            out_ranges.push(Range {
-                start: curr,
-                end: map_range.src_start,
+    pub fn from_ranges(map_ranges: &[MapRange]) -> Mapping {
+        let mut sorted: Vec<&MapRange> = map_ranges.iter().collect();
+        sorted.sort_by_key(|r| r.src_start);
+
+        let mut pieces = Vec::new();
+        let mut curr = 0;
+        for range in sorted {
+            if curr < range.src_start {
+                pieces.push(Piece {
+                    start: curr,
+                    end: range.src_start,
+                    offset: 0,
+                });
+            }
+            pieces.push(Piece {
+                start: range.src_start,
+                end: range.src_start + range.length,
+                offset: range.dst_start as i64 - range.src_start as i64,
             });
-            curr = map_range.src_start;
+            curr = range.src_start + range.length;
         }
-        let offset = curr - map_range.src_start;
-        if map_range.src_start + map_range.length < end {
-            let new_range = Range {
-                start: map_range.dst_start + offset,
-                end: map_range.dst_start + map_range.length,
-            };
-            out_ranges.push(new_range);
-        } else {
-            let dist = end - map_range.src_start;
-            let new_range = Range {
-                start: map_range.dst_start + offset,
-                end: map_range.dst_start + dist,
+        pieces.push(Piece {
+            start: curr,
+            end: u64::MAX,
+            offset: 0,
+        });
+
+        let mut mapping = Mapping { pieces };
+        mapping.merge();
+        mapping
+    }
+
+    // Joins neighbouring pieces that shift by the same amount, and drops
+    // empty ones.
+    fn merge(&mut self) {
+        let mut merged: Vec<Piece> = Vec::new();
+        for piece in self.pieces.drain(..) {
+            if piece.start == piece.end {
+                continue;
+            }
+            match merged.last_mut() {
+                Some(last) if last.offset == piece.offset && last.end == piece.start => {
+                    last.end = piece.end;
+                }
+                _ => merged.push(piece),
+            }
+        }
+        self.pieces = merged;
+    }
+
+    pub fn apply(&self, x: u64) -> u64 {
+        let idx = self.pieces.partition_point(|p| p.end <= x);
+        match self.pieces.get(idx) {
+            Some(piece) => shift(x, piece.offset),
+            None => x,
+        }
+    }
+
+    pub fn apply_range(&self, range: Range) -> Vec<Range> {
+        if range.start >= range.end {
+            return Vec::new();
+        }
+
+        let first = self.pieces.partition_point(|p| p.end <= range.start);
+        self.pieces[first..]
+            .iter()
+            .take_while(|p| p.start < range.end)
+            .map(|p| Range {
+                start: shift(u64::max(p.start, range.start), p.offset),
+                end: shift(u64::min(p.end, range.end), p.offset),
+            })
+            .collect()
+    }
+
+    // The mapping that applies self and then next.
+    pub fn then(&self, next: &Mapping) -> Mapping {
+        let mut pieces = Vec::new();
+        for piece in &self.pieces {
+            let image = Range {
+                start: shift(piece.start, piece.offset),
+                end: shift(piece.end, piece.offset),
             };
-            out_ranges.push(new_range);
+            let first = next.pieces.partition_point(|p| p.end <= image.start);
+            for other in next.pieces[first..]
+                .iter()
+                .take_while(|p| p.start < image.end)
+            {
+                pieces.push(Piece {
+                    start: shift(u64::max(other.start, image.start), -piece.offset),
+                    end: shift(u64::min(other.end, image.end), -piece.offset),
+                    offset: piece.offset + other.offset,
+                });
+            }
+        }
+
+        let mut mapping = Mapping { pieces };
+        mapping.merge();
+        mapping
+    }
+
+    // The reverse mapping, or None if two inputs share an output or some
+    // output can't be reached.
+    pub fn invert(&self) -> Option<Mapping> {
+        let mut pieces: Vec<Piece> = self
+            .pieces
+            .iter()
+            .map(|p| Piece {
+                start: shift(p.start, p.offset),
+                end: shift(p.end, p.offset),
+                offset: -p.offset,
+            })
+            .collect();
+        pieces.sort_by_key(|p| p.start);
+
+        let mut curr = 0;
+        for piece in &pieces {
+            if piece.start != curr || piece.end < piece.start {
+                return None;
+            }
+            curr = piece.end;
+        }
+        if curr != u64::MAX {
+            return None;
+        }
+
+        let mut mapping = Mapping { pieces };
+        mapping.merge();
+        Some(mapping)
+    }
+}
+
+impl fmt::Display for Mapping {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for piece in &self.pieces {
+            writeln!(
+                f,
+                "{}..{} -> {}..{} ({:+})",
+                piece.start,
+                piece.end,
+                shift(piece.start, piece.offset),
+                shift(piece.end, piece.offset),
+                piece.offset
+            )?;
         }
-        curr = map_range.src_start + map_range.length;
-        map = map_it.next();
+        Ok(())
     }
+}
 
-    out_ranges
+pub struct Input {
+    seeds: Vec<u64>,
+    // Every stage from seed to location composed into one.
+    mapping: Mapping,
 }
 
-pub fn parse_input(input: &str) -> Input {
-    let mut lines = input.lines();
-    let first = lines.next().unwrap().strip_prefix("seeds: ").unwrap();
-    let seeds = first.split(' ').map(|s| s.parse().unwrap()).collect();
+// Every value stays below this, so a start plus a length and the offset
+// between any two values both fit in an i64.
+const LIMIT: u64 = 1 << 62;
 
-    let mut curr_map = Vec::new();
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
+    let mut lines = numbered_lines(input);
+    let (n, first) = lines.next().ok_or(ParseError::new(0, "empty input"))?;
+    let seeds: Vec<u64> = first
+        .strip_prefix("seeds: ")
+        .ok_or(ParseError::new(n, "expected seeds"))?
+        .split(' ')
+        .map(|s| number(n, s))
+        .collect::<Result<_, _>>()?;
+    if !seeds.len().is_multiple_of(2) {
+        return Err(ParseError::new(n, "expected seeds in pairs"));
+    }
+    if seeds.iter().any(|&s| s >= LIMIT) {
+        return Err(ParseError::new(n, "seed too large"));
+    }
+
+    let mut curr_map: Vec<MapRange> = Vec::new();
     let mut maps = Vec::new();
-    let lines = lines.skip(2);
-    for line in lines {
+    for (n, line) in lines {
         if line.is_empty() {
-            curr_map.sort_by_key(|r: &MapRange| r.src_start);
-            maps.push(curr_map);
+            if !curr_map.is_empty() {
+                maps.push(Mapping::from_ranges(&curr_map));
+            }
             curr_map = Vec::new();
             continue;
         }
@@ -85,69 +235,160 @@ pub fn parse_input(input: &str) -> Input {
             continue;
         }
 
-        let mut parsed = line.split_whitespace().map(|n| n.parse().unwrap());
+        let nums: Vec<u64> = line
+            .split_whitespace()
+            .map(|s| number(n, s))
+            .collect::<Result<_, _>>()?;
+        let &[dst_start, src_start, length] = nums.as_slice() else {
+            return Err(ParseError::new(n, "expected three numbers"));
+        };
+        if [dst_start, src_start, length].iter().any(|&x| x >= LIMIT) {
+            return Err(ParseError::new(n, "number too large"));
+        }
         let range = MapRange {
-            dst_start: parsed.next().unwrap(),
-            src_start: parsed.next().unwrap(),
-            length: parsed.next().unwrap(),
+            dst_start,
+            src_start,
+            length,
         };
+        if curr_map.iter().any(|r| {
+            r.src_start < range.src_start + range.length && range.src_start < r.src_start + r.length
+        }) {
+            return Err(ParseError::new(n, "overlapping ranges"));
+        }
         curr_map.push(range);
     }
+    if !curr_map.is_empty() {
+        maps.push(Mapping::from_ranges(&curr_map));
+    }
 
-    Input { seeds, maps }
-}
+    let mapping = maps
+        .iter()
+        .fold(Mapping::identity(), |acc, map| acc.then(map));
 
-#[allow(unused_variables)]
-pub fn part1(input: &Input) -> Option<u64> {
-    let mapped_nums = input.seeds.iter().map(|&src| {
-        let mut s = src;
-        for map in &input.maps {
-            for map_range in map {
-                if map_range.src_start <= s && s < map_range.src_start + map_range.length {
-                    let dst = s - map_range.src_start + map_range.dst_start;
-                    s = dst;
-                    break;
-                }
-            }
-        }
-        s
-    });
+    Ok(Input { seeds, mapping })
+}
 
-    mapped_nums.min()
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    generate_with(rng, size, 1 << 32)
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &Input) -> Option<u64> {
-    let mut ranges = Vec::new();
-    let mut i = 0;
-    while i < input.seeds.len() {
-        let (start, length) = (input.seeds[i], input.seeds[i + 1]);
-        ranges.push(Range {
-            start,
-            end: start + length,
-        });
-        i += 2;
-    }
+// Seeds and the seven maps of an almanac, with every value below `max`, which
+// is 2^32 in the real one. Source ranges within a map never overlap.
+pub fn generate_with(rng: &mut Rng, size: usize, max: i64) -> String {
+    const STAGES: [&str; 8] = [
+        "seed",
+        "soil",
+        "fertilizer",
+        "water",
+        "light",
+        "temperature",
+        "humidity",
+        "location",
+    ];
 
-    for map in &input.maps {
-        let mut new_ranges = Vec::new();
-        for range in &ranges {
-            let mut mapped_ranges = map_range(range, map);
-            new_ranges.append(&mut mapped_ranges);
+    let seeds: Vec<String> = (0..(size / 4).clamp(1, 10))
+        .map(|_| format!("{} {}", rng.range(0..max / 2), rng.range(0..max / 8)))
+        .collect();
+    let mut out = format!("seeds: {}\n", seeds.join(" "));
+
+    for stage in STAGES.windows(2) {
+        out.push_str(&format!("\n{}-to-{} map:\n", stage[0], stage[1]));
+
+        let mut cuts: Vec<i64> = (0..2 * size.max(1)).map(|_| rng.range(0..max)).collect();
+        cuts.sort();
+        cuts.dedup();
+        for pair in cuts.chunks_exact(2) {
+            let (src, length) = (pair[0], pair[1] - pair[0]);
+            let dst = rng.range(0..max - length);
+            out.push_str(&format!("{} {} {}\n", dst, src, length));
         }
-        ranges = new_ranges;
     }
 
-    ranges.iter().map(|r| r.start).min()
+    out
+}
+
+// Slow but obvious versions of the parts for cross-checking: every seed goes
+// through the maps one at a time, and part 2 tries every seed in every range.
+#[cfg(test)]
+fn reference_maps(data: &str) -> (Vec<u64>, Vec<Vec<[u64; 3]>>) {
+    let mut blocks = data.split("\n\n");
+    let seeds = blocks
+        .next()
+        .unwrap()
+        .split_whitespace()
+        .skip(1)
+        .map(|n| n.parse().unwrap())
+        .collect();
+    let maps = blocks
+        .map(|block| {
+            block
+                .lines()
+                .skip(1)
+                .map(|line| {
+                    let mut nums = line.split_whitespace().map(|n| n.parse().unwrap());
+                    [(); 3].map(|_| nums.next().unwrap())
+                })
+                .collect()
+        })
+        .collect();
+
+    (seeds, maps)
+}
+
+#[cfg(test)]
+fn reference_location(maps: &[Vec<[u64; 3]>], seed: u64) -> u64 {
+    maps.iter().fold(seed, |value, map| {
+        map.iter()
+            .find(|&&[_, src, len]| src <= value && value < src + len)
+            .map_or(value, |&[dst, src, _]| dst + value - src)
+    })
+}
+
+#[cfg(test)]
+pub fn reference_part1(data: &str) -> Option<u64> {
+    let (seeds, maps) = reference_maps(data);
+    seeds.iter().map(|&s| reference_location(&maps, s)).min()
+}
+
+#[cfg(test)]
+pub fn reference_part2(data: &str) -> Option<u64> {
+    let (seeds, maps) = reference_maps(data);
+    seeds
+        .chunks(2)
+        .flat_map(|pair| pair[0]..pair[0] + pair[1])
+        .map(|s| reference_location(&maps, s))
+        .min()
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &Input) -> Option<u64> {
+    input.seeds.iter().map(|&s| input.mapping.apply(s)).min()
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &Input) -> Option<u64> {
+    input
+        .seeds
+        .chunks(2)
+        .flat_map(|pair| {
+            input.mapping.apply_range(Range {
+                start: pair[0],
+                end: pair[0] + pair[1],
+            })
+        })
+        .map(|r| r.start)
+        .min()
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
+    use crate::utils::property::assert_agree;
     const TEST_INPUT: &str = include_str!("../../input/day05/test.txt");
     #[test]
     fn test_day05_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -156,10 +397,62 @@ pub mod tests {
 
     #[test]
     fn test_day05_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
-        assert_eq!(resp, None);
+        assert_eq!(resp, Some(46));
+    }
+
+    #[test]
+    fn test_day05_mapping() {
+        let input = parse_input(TEST_INPUT).unwrap();
+        let mapping = &input.mapping;
+
+        // Seed to location for each seed in the example.
+        for (seed, location) in [(79, 82), (14, 43), (55, 86), (13, 35)] {
+            assert_eq!(mapping.apply(seed), location);
+        }
+
+        let inverse = mapping.invert().unwrap();
+        for seed in 0..200 {
+            assert_eq!(inverse.apply(mapping.apply(seed)), seed);
+        }
+        assert_eq!(inverse.invert().as_ref(), Some(mapping));
+
+        let ranges = mapping.apply_range(Range { start: 82, end: 83 });
+        assert_eq!(ranges, vec![Range { start: 46, end: 47 }]);
+
+        assert_eq!(mapping.to_string().lines().count(), mapping.pieces.len());
+    }
+    #[test]
+    fn test_day05_reference() {
+        assert_eq!(reference_part1(TEST_INPUT), Some(35));
+        assert_eq!(reference_part2(TEST_INPUT), Some(46));
+
+        // A seed range of length zero holds no seeds.
+        let empty = TEST_INPUT.replacen("55 13", "55 13 13 0", 1);
+        assert_eq!(part2(&parse_input(&empty).unwrap()), Some(46));
+        assert_eq!(reference_part2(&empty), Some(46));
+
+        let generate = |rng: &mut Rng| generate_with(rng, 8, 64);
+        assert_agree(
+            200,
+            generate,
+            |s| part1(&parse_input(s).unwrap()),
+            reference_part1,
+        );
+        assert_agree(
+            200,
+            generate,
+            |s| part2(&parse_input(s).unwrap()),
+            reference_part2,
+        );
+    }
+
+    #[test]
+    fn test_day05_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
     }
 }
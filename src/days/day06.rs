@@ -1,101 +1,214 @@
-pub fn parse_input(input: &str) -> &str {
-    input
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    rng::Rng,
+};
+
+pub struct Input {
+    races: Vec<(u64, u64)>,
+    // None if the joined numbers don't fit in a u64.
+    joined: Option<(u64, u64)>,
 }
 
-#[allow(unused_variables)]
-pub fn part1(input: &str) -> Option<u64> {
-    let mut nums = Vec::new();
-    for line in input.lines() {
-        let mut chars = line.chars();
-        while let Some(c) = chars.next() {
-            match c {
-                '0'..='9' => {
-                    let mut v = c as u64 - '0' as u64;
-                    while let Some(c) = chars.next() {
-                        if c.is_ascii_digit() {
-                            v = v * 10 + c as u64 - '0' as u64;
-                        } else {
-                            break;
-                        }
-                    }
-                    nums.push(v);
-                }
-                _ => continue,
-            }
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
+    let mut lines = numbered_lines(input).map(|(n, line)| {
+        let (_, nums) = line
+            .split_once(':')
+            .ok_or(ParseError::new(n, "expected a label"))?;
+        let split: Vec<u64> = nums
+            .split_whitespace()
+            .map(|s| number(n, s))
+            .collect::<Result<_, _>>()?;
+        if split.is_empty() {
+            return Err(ParseError::new(n, "expected a number"));
         }
+        let joined = nums.split_whitespace().collect::<String>().parse().ok();
+        Ok((split, joined))
+    });
+
+    let (times, time) = lines.next().ok_or(ParseError::new(0, "missing times"))??;
+    let (dists, dist) = lines
+        .next()
+        .ok_or(ParseError::new(0, "missing distances"))??;
+    if times.len() != dists.len() {
+        return Err(ParseError::new(2, "expected a distance for every time"));
+    }
+
+    Ok(Input {
+        races: times.into_iter().zip(dists).collect(),
+        joined: time.zip(dist),
+    })
+}
+
+// Counts the hold times x with x * (time - x) > dist. The winning times are
+// symmetric around time / 2, so only the lowest one is needed. The integer
+// square root of the discriminant gets within a step of it, and the exact
+// comparison fixes it up, including when a root lands exactly on the record.
+pub fn ways_to_win(time: u64, dist: u64) -> u64 {
+    let beats = |x: u64| x as u128 * (time - x) as u128 > dist as u128;
+
+    let time_sq = time as u128 * time as u128;
+    let dist4 = 4 * dist as u128;
+    if time_sq < dist4 {
+        return 0;
     }
+    let root = (time_sq - dist4).isqrt() as u64;
 
-    let mut vals = Vec::new();
-    let count = nums.len() >> 1;
-    for i in 0..count {
-        vals.push((nums[i], nums[i + count]));
+    let mut low = (time - root) / 2;
+    while low <= time / 2 && !beats(low) {
+        low += 1;
+    }
+    while low > 0 && beats(low - 1) {
+        low -= 1;
     }
 
-    let mut prod = 1;
-    for (time, dist) in vals {
-        let possible = (0..time)
-            .map(|i| (time - i) * i)
-            .filter(|&d| d > dist)
-            .count();
-        prod *= possible as u64;
+    if low > time / 2 || !beats(low) {
+        return 0;
     }
 
+    time - 2 * low + 1
+}
+
+// Part 2 joins all the races into one, so only a handful of short races fit
+// in a u64 however big `size` is.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let times: Vec<i64> = (0..size.clamp(1, 4)).map(|_| rng.range(10..100)).collect();
+    let dists: Vec<i64> = times.iter().map(|&t| rng.range(1..t * t / 4)).collect();
+
+    let format = |nums: &[i64]| nums.iter().map(|n| format!("{:>5}", n)).collect::<String>();
+    format!("Time:    {}\nDistance:{}\n", format(&times), format(&dists))
+}
+
+// Tries every hold time, for cross-checking against `ways_to_win`.
+#[cfg(test)]
+fn reference_ways(time: u64, dist: u64) -> u64 {
+    (0..=time).filter(|x| x * (time - x) > dist).count() as u64
+}
+
+#[cfg(test)]
+pub fn reference_part1(data: &str) -> Option<u64> {
+    let input = parse_input(data).ok()?;
+    let prod = input
+        .races
+        .iter()
+        .map(|&(time, dist)| reference_ways(time, dist))
+        .product();
+
     Some(prod)
 }
 
+#[cfg(test)]
+pub fn reference_part2(data: &str) -> Option<u64> {
+    let (time, dist) = parse_input(data).ok()?.joined?;
+
+    Some(reference_ways(time, dist))
+}
+
 #[allow(unused_variables)]
-pub fn part2(input: &str) -> Option<u64> {
-    let mut nums = [0; 2];
-    let mut i = 0;
-    for line in input.lines() {
-        let mut chars = line.chars();
-        while let Some(c) = chars.next() {
-            match c {
-                '0'..='9' => {
-                    let mut v = c as u64 - '0' as u64;
-                    while let Some(c) = chars.next() {
-                        if c.is_ascii_digit() {
-                            v = v * 10 + c as u64 - '0' as u64;
-                        } else {
-                            continue;
-                        }
-                    }
-                    nums[i] = v;
-                    i += 1;
-                }
-                _ => continue,
-            }
-        }
-    }
-    let time = nums[0];
-    let dist = nums[1];
+pub fn part1(input: &Input) -> Option<u64> {
+    let prod = input
+        .races
+        .iter()
+        .map(|&(time, dist)| ways_to_win(time, dist))
+        .product();
+
+    Some(prod)
+}
 
-    // Use quadratic formula and find roots of formula x * (time - x) = dist
-    let first_root = (time as f64 - f64::sqrt((time * time - 4 * dist) as f64)) / 2.0;
-    let second_root = time - f64::ceil(first_root) as u64;
+#[allow(unused_variables)]
+pub fn part2(input: &Input) -> Option<u64> {
+    let (time, dist) = input.joined?;
 
-    Some(second_root - first_root as u64)
+    Some(ways_to_win(time, dist))
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
+    use crate::utils::property::assert_agree;
     const TEST_INPUT: &str = include_str!("../../input/day06/test.txt");
     #[test]
     fn test_day06_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
-        let resp = part1(input);
+        let resp = part1(&input);
 
         assert_eq!(resp, Some(288));
     }
 
     #[test]
     fn test_day06_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
-        let resp = part2(input);
+        let resp = part2(&input);
 
         assert_eq!(resp, Some(71503));
     }
+
+    #[test]
+    fn test_day06_ways_to_win() {
+        // Roots at 10 and 20, which only tie the record.
+        assert_eq!(ways_to_win(30, 200), 9);
+        assert_eq!(ways_to_win(4, 4), 0);
+        assert_eq!(ways_to_win(4, 3), 1);
+        assert_eq!(ways_to_win(10, 24), 1);
+        assert_eq!(ways_to_win(0, 0), 0);
+        assert_eq!(ways_to_win(1, 0), 0);
+        assert_eq!(ways_to_win(2, 0), 1);
+
+        for time in 0..50 {
+            for dist in 0..700 {
+                let expected = (0..=time).filter(|x| x * (time - x) > dist).count();
+                assert_eq!(ways_to_win(time, dist), expected as u64);
+            }
+        }
+
+        assert_eq!(ways_to_win(u64::MAX, 0), u64::MAX - 1);
+        // Too large for f64 to tell the tie apart.
+        assert_eq!(ways_to_win(1 << 32, (1 << 62) - 1), 1);
+        assert_eq!(ways_to_win(1 << 32, 1 << 62), 0);
+        assert_eq!(ways_to_win((1 << 32) + 1, (1 << 62) + (1 << 31) - 1), 2);
+    }
+    #[test]
+    fn test_day06_reference() {
+        assert_eq!(reference_part1(TEST_INPUT), Some(288));
+        assert_eq!(reference_part2(TEST_INPUT), Some(71503));
+
+        // Short races so the joined one is still quick to brute force, with
+        // records up to and just past the best possible distance.
+        let generate = |rng: &mut Rng| {
+            let times: Vec<i64> = (0..rng.range(1..4)).map(|_| rng.range(1..10)).collect();
+            let dists: Vec<i64> = times.iter().map(|&t| rng.range(0..t * t / 4 + 2)).collect();
+            let format = |nums: &[i64]| nums.iter().map(|n| format!(" {}", n)).collect::<String>();
+            format!("Time:{}\nDistance:{}\n", format(&times), format(&dists))
+        };
+        assert_agree(
+            200,
+            generate,
+            |s| part1(&parse_input(s).unwrap()),
+            reference_part1,
+        );
+        assert_agree(
+            200,
+            generate,
+            |s| part2(&parse_input(s).unwrap()),
+            reference_part2,
+        );
+    }
+
+    #[test]
+    fn test_day06_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
+
+    #[test]
+    fn test_day06_joined_overflow() {
+        // The joined race is far too long for a u64, but the others are fine.
+        let long = "Time: 7 15 30 7 15 30 7 15 30 7 15\n\
+                    Distance: 9 40 200 9 40 200 9 40 200 9 40\n";
+        let input = parse_input(long).unwrap();
+        assert_eq!(part1(&input), Some(288 * 288 * 288 * 32));
+        assert_eq!(part2(&input), None);
+    }
 }
@@ -1,137 +1,178 @@
-use std::{cmp::Ordering, collections::HashMap};
+use std::collections::HashSet;
 
-pub struct Hand {
-    raw: [char; 5],
-    bid: u64,
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    rng::Rng,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HandType {
+    HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
 }
 
-pub fn parse_input(input: &str) -> Vec<Hand> {
-    let mut hands = Vec::new();
-    for line in input.lines() {
-        let mut chars = line.chars();
-        let mut raw = ['0'; 5];
-        for i in 0..5 {
-            let c = chars.next().unwrap();
-            raw[i] = c;
-        }
+// Sizes of the groups of matching cards in a hand, largest first. Hands
+// compare by shape before their cards: the bigger largest group wins, then the
+// bigger second group and so on. For five cards that's the usual order of hand
+// types, and it carries over to hands of any size.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Shape(Vec<usize>);
 
-        chars.next();
-        let mut bid = 0;
-        for c in chars {
-            bid = 10 * bid + c as u64 - '0' as u64;
+impl Shape {
+    // Counts per card in any order, with wildcards added to the largest group.
+    pub fn from_counts(counts: &[usize], wildcards: usize) -> Shape {
+        let mut groups = counts.to_vec();
+        groups.sort_by(|a, b| b.cmp(a));
+        match groups.first_mut() {
+            Some(largest) => *largest += wildcards,
+            None => groups.push(wildcards),
         }
+        groups.retain(|&n| n > 0);
 
-        let hand = Hand { raw, bid };
-        hands.push(hand);
+        Shape(groups)
     }
 
-    hands
+    // The named type of a five card hand, None for other sizes.
+    pub fn hand_type(&self) -> Option<HandType> {
+        let hand_type = match self.0.as_slice() {
+            [5] => HandType::FiveOfAKind,
+            [4, 1] => HandType::FourOfAKind,
+            [3, 2] => HandType::FullHouse,
+            [3, 1, 1] => HandType::ThreeOfAKind,
+            [2, 2, 1] => HandType::TwoPair,
+            [2, 1, 1, 1] => HandType::OnePair,
+            [1, 1, 1, 1, 1] => HandType::HighCard,
+            _ => return None,
+        };
+
+        Some(hand_type)
+    }
 }
 
-#[allow(unused_variables)]
-pub fn part1(input: &[Hand]) -> Option<u64> {
-    let mut parsed_hands = Vec::new();
-    for hand in input {
-        let mut ranked = [0; 5];
-        let mut map = HashMap::new();
-        for (i, c) in hand.raw.iter().enumerate() {
-            let rank = match c {
-                '0'..='9' => *c as u8 - '0' as u8,
-                'T' => 10,
-                'J' => 11,
-                'Q' => 12,
-                'K' => 13,
-                'A' => 14,
-                _ => unreachable!(),
-            };
-            ranked[i] = rank;
-            map.entry(c).and_modify(|e| *e += 1).or_insert(1);
+pub struct Ruleset {
+    // Weakest card first.
+    pub card_order: &'static str,
+    // Cards that join whichever group makes the hand strongest.
+    pub wildcards: &'static str,
+    pub hand_size: usize,
+}
+
+pub const STANDARD: Ruleset = Ruleset {
+    card_order: "23456789TJQKA",
+    wildcards: "",
+    hand_size: 5,
+};
+
+pub const JOKERS: Ruleset = Ruleset {
+    card_order: "J23456789TQKA",
+    wildcards: "J",
+    hand_size: 5,
+};
+
+pub struct Hand {
+    raw: Vec<char>,
+    bid: u64,
+}
+
+impl Hand {
+    // Returns the hand's shape and the rank of each card, which together order
+    // hands from weakest to strongest. None if the hand doesn't fit the rules.
+    pub fn evaluate(&self, rules: &Ruleset) -> Option<(Shape, Vec<usize>)> {
+        if self.raw.len() != rules.hand_size {
+            return None;
         }
 
-        let mut counts = [0; 5];
-        for (i, &count) in map.values().enumerate() {
-            counts[i] = count;
+        let mut ranks = Vec::new();
+        let mut counts = vec![0; rules.card_order.len()];
+        let mut wildcards = 0;
+        for &c in &self.raw {
+            let rank = rules.card_order.find(c)?;
+            ranks.push(rank);
+            if rules.wildcards.contains(c) {
+                wildcards += 1;
+            } else {
+                counts[rank] += 1;
+            }
         }
-        counts.sort_by(|a, b| b.cmp(a));
 
-        parsed_hands.push((counts, ranked, hand.bid));
+        Some((Shape::from_counts(&counts, wildcards), ranks))
     }
+}
 
-    parsed_hands.sort_by(|(a_counts, a_ranked, _), (b_counts, b_ranked, _)| {
-        let ord = a_counts.cmp(b_counts);
-        match ord {
-            Ordering::Less | Ordering::Greater => ord,
-            Ordering::Equal => a_ranked.cmp(b_ranked),
-        }
-    });
+pub fn total_winnings(hands: &[Hand], rules: &Ruleset) -> Option<u64> {
+    let mut evaluated = hands
+        .iter()
+        .map(|hand| Some((hand.evaluate(rules)?, hand.bid)))
+        .collect::<Option<Vec<_>>>()?;
+    evaluated.sort();
 
-    let sum = parsed_hands
+    let sum = evaluated
         .iter()
         .enumerate()
-        .map(|(rank, (_, _, bid))| (rank as u64 + 1) * bid)
+        .map(|(rank, (_, bid))| (rank as u64 + 1) * bid)
         .sum();
     Some(sum)
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &[Hand]) -> Option<u64> {
-    let mut parsed_hands = Vec::new();
-    for hand in input {
-        let mut ranked = [0; 5];
-        let mut map = HashMap::new();
-        let mut jokers = 0;
-        for (i, c) in hand.raw.iter().enumerate() {
-            let rank = match c {
-                '0'..='9' => *c as u8 - '0' as u8,
-                'T' => 10,
-                'J' => 1,
-                'Q' => 12,
-                'K' => 13,
-                'A' => 14,
-                _ => unreachable!(),
-            };
-            ranked[i] = rank;
+// Cards aren't checked here, since which ones are allowed depends on the
+// rules, see `Hand::evaluate`.
+pub fn parse_input(input: &str) -> Result<Vec<Hand>, ParseError> {
+    let mut hands = Vec::new();
+    for (n, line) in numbered_lines(input) {
+        let (raw, bid) = line
+            .split_once(' ')
+            .ok_or(ParseError::new(n, "expected a hand and a bid"))?;
 
-            if *c == 'J' {
-                jokers += 1;
-            } else {
-                map.entry(c).and_modify(|e| *e += 1).or_insert(1);
-            }
-        }
+        let hand = Hand {
+            raw: raw.chars().collect(),
+            bid: number(n, bid)?,
+        };
+        hands.push(hand);
+    }
 
-        let mut counts = [0; 5];
-        for (i, &count) in map.values().enumerate() {
-            counts[i] = count;
-        }
-        counts.sort_by(|a, b| b.cmp(a));
-        counts[0] += jokers;
+    Ok(hands)
+}
 
-        parsed_hands.push((counts, ranked, hand.bid));
-    }
+// Distinct random hands, since two equal hands would tie and make the ranking
+// ambiguous.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    const CARDS: &[u8] = b"23456789TJQKA";
 
-    parsed_hands.sort_by(|(a_counts, a_ranked, _), (b_counts, b_ranked, _)| {
-        let ord = a_counts.cmp(b_counts);
-        match ord {
-            Ordering::Less | Ordering::Greater => ord,
-            Ordering::Equal => a_ranked.cmp(b_ranked),
+    let mut seen = HashSet::new();
+    let mut out = String::new();
+    while seen.len() < size.min(CARDS.len().pow(5)) {
+        let hand: String = (0..5).map(|_| char::from(*rng.choose(CARDS))).collect();
+        if seen.insert(hand.clone()) {
+            out.push_str(&format!("{} {}\n", hand, rng.range(1..1001)));
         }
-    });
+    }
 
-    let sum = parsed_hands
-        .iter()
-        .enumerate()
-        .map(|(rank, (_, _, bid))| (rank as u64 + 1) * bid)
-        .sum();
-    Some(sum)
+    out
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &[Hand]) -> Option<u64> {
+    total_winnings(input, &STANDARD)
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &[Hand]) -> Option<u64> {
+    total_winnings(input, &JOKERS)
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day07/test.txt");
     #[test]
     fn test_day07_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -140,10 +181,125 @@ pub mod tests {
 
     #[test]
     fn test_day07_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(5905));
     }
+
+    // Classifies a hand without wildcards from how many distinct cards it has
+    // and how many copies of the most common one.
+    fn expected_type(cards: &[char]) -> HandType {
+        let mut distinct = cards.to_vec();
+        distinct.sort();
+        distinct.dedup();
+        let most = distinct
+            .iter()
+            .map(|c| cards.iter().filter(|&d| d == c).count())
+            .max()
+            .unwrap();
+
+        match (distinct.len(), most) {
+            (1, _) => HandType::FiveOfAKind,
+            (2, 4) => HandType::FourOfAKind,
+            (2, _) => HandType::FullHouse,
+            (3, 3) => HandType::ThreeOfAKind,
+            (3, _) => HandType::TwoPair,
+            (4, _) => HandType::OnePair,
+            _ => HandType::HighCard,
+        }
+    }
+
+    // Every multiset of five cards, as sorted indices into the card order.
+    fn multisets(cards: usize, size: usize) -> Vec<Vec<usize>> {
+        if size == 0 {
+            return vec![vec![]];
+        }
+        let mut out = Vec::new();
+        for smaller in multisets(cards, size - 1) {
+            let start = smaller.last().copied().unwrap_or(0);
+            for card in start..cards {
+                let mut next = smaller.clone();
+                next.push(card);
+                out.push(next);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_day07_all_hand_types() {
+        let hands = multisets(13, 5);
+        assert_eq!(hands.len(), 6188);
+
+        for idxs in hands {
+            let cards: Vec<char> = idxs
+                .iter()
+                .map(|&i| STANDARD.card_order.as_bytes()[i] as char)
+                .collect();
+            let hand = Hand {
+                raw: cards.clone(),
+                bid: 0,
+            };
+
+            let (shape, _) = hand.evaluate(&STANDARD).unwrap();
+            assert_eq!(
+                shape.hand_type(),
+                Some(expected_type(&cards)),
+                "{:?}",
+                cards
+            );
+
+            // Jokers are worth whatever other card gives the best hand.
+            let best = "23456789TQKA"
+                .chars()
+                .map(|sub| {
+                    let subbed: Vec<char> = cards
+                        .iter()
+                        .map(|&c| if c == 'J' { sub } else { c })
+                        .collect();
+                    expected_type(&subbed)
+                })
+                .max()
+                .unwrap();
+            let (shape, _) = hand.evaluate(&JOKERS).unwrap();
+            assert_eq!(shape.hand_type(), Some(best), "{:?}", cards);
+        }
+    }
+
+    #[test]
+    fn test_day07_other_hand_sizes() {
+        let rules = Ruleset {
+            hand_size: 6,
+            ..JOKERS
+        };
+        let hand = |raw: &str| Hand {
+            raw: raw.chars().collect(),
+            bid: 0,
+        };
+        let shape = |raw: &str| hand(raw).evaluate(&rules).unwrap().0;
+
+        assert_eq!(shape("JJJJJJ"), Shape(vec![6]));
+        assert_eq!(shape("22J333"), Shape(vec![4, 2]));
+        assert_eq!(shape("22J333").hand_type(), None);
+        assert!(shape("222333") < shape("222234"));
+        assert!(shape("223344") < shape("222334"));
+        assert!(shape("234567") < shape("23456J"));
+        assert_eq!(hand("22333").evaluate(&rules), None);
+
+        let hands = [hand("AKQT98"), hand("22J333"), hand("222333")];
+        let hands: Vec<Hand> = hands
+            .into_iter()
+            .zip([1, 10, 100])
+            .map(|(hand, bid)| Hand { bid, ..hand })
+            .collect();
+        assert_eq!(total_winnings(&hands, &rules), Some(1 + 2 * 100 + 3 * 10));
+    }
+
+    #[test]
+    fn test_day07_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
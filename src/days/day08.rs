@@ -1,4 +1,8 @@
-use num::integer::lcm;
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
+use num::integer::{lcm, ExtendedGcd, Integer};
 use std::collections::HashMap;
 
 pub enum Direction {
@@ -20,32 +24,44 @@ pub struct Input {
     map: HashMap<String, Node>,
 }
 
-pub fn parse_input(input: &str) -> Input {
-    let mut it = input.lines();
-    let first = it.next().unwrap();
-    let instructions = first
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
+    let mut it = numbered_lines(input);
+    let (n, first) = it.next().ok_or(ParseError::new(0, "empty input"))?;
+    let instructions: Vec<Direction> = first
         .chars()
         .map(|c| match c {
-            'L' => Direction::Left,
-            'R' => Direction::Right,
-            _ => unreachable!(),
+            'L' => Ok(Direction::Left),
+            'R' => Ok(Direction::Right),
+            _ => Err(ParseError::new(n, "expected L or R")),
         })
-        .collect();
-    it.next();
+        .collect::<Result<_, _>>()?;
+    if instructions.is_empty() {
+        return Err(ParseError::new(n, "expected L or R"));
+    }
+    if let Some((n, line)) = it.next() {
+        if !line.is_empty() {
+            return Err(ParseError::new(n, "expected a blank line"));
+        }
+    }
 
     let mut map = HashMap::new();
-    for line in it {
-        let (name, line) = line.split_at(3);
-        let (_, line) = line.split_at(4);
-        let (left, line) = line.split_at(3);
-        let (_, line) = line.split_at(2);
-        let (right, _) = line.split_at(3);
+    let mut exits = Vec::new();
+    for (n, line) in it {
+        let (name, rest) = line
+            .split_once(" = ")
+            .ok_or(ParseError::new(n, "expected a node"))?;
+        let (left, right) = rest
+            .strip_prefix('(')
+            .and_then(|rest| rest.strip_suffix(')'))
+            .and_then(|rest| rest.split_once(", "))
+            .ok_or(ParseError::new(n, "expected a pair of exits"))?;
 
         let name = name.to_string();
         let left = left.to_string();
         let right = right.to_string();
         let a_node = name.ends_with('A');
         let z_node = name.ends_with('Z');
+        exits.push((n, left.clone(), right.clone()));
 
         let node = Node {
             name: name.clone(),
@@ -55,13 +71,75 @@ pub fn parse_input(input: &str) -> Input {
             z_node,
         };
 
-        // println!("{:?}", node);
+        if map.insert(name, node).is_some() {
+            return Err(ParseError::new(n, "node defined twice"));
+        }
+    }
+
+    for (n, left, right) in exits {
+        if !map.contains_key(&left) || !map.contains_key(&right) {
+            return Err(ParseError::new(n, "exit to an unknown node"));
+        }
+    }
+
+    Ok(Input { instructions, map })
+}
+
+// Six ghosts built like the real maps: each start leads into a loop that ends
+// in its only Z node, and every loop is a prime multiple of the number of
+// instructions long. Nodes on a loop come in pairs with the same exits, so
+// the route doesn't depend on which way the instructions turn.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let mut primes = [3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59];
+    rng.shuffle(&mut primes);
+    let loops = &primes[..6];
+
+    // Names that don't end in A or Z, of which there are 26 * 26 * 24.
+    let name = |k: usize| {
+        let letters = [
+            b'A' + (k / (24 * 26) % 26) as u8,
+            b'A' + (k / 24 % 26) as u8,
+            b'B' + (k % 24) as u8,
+        ];
+        String::from_utf8(letters.to_vec()).unwrap()
+    };
+    let len = (size.min(15000) / (2 * loops.iter().sum::<usize>())).max(1);
+    let instructions: String = (0..len).map(|_| *rng.choose(&['L', 'R'])).collect();
+
+    let mut next_name = 0;
+    let mut lines = Vec::new();
+    for (g, &k) in loops.iter().enumerate() {
+        let (start, end) = match g {
+            0 => ("AAA".to_string(), "ZZZ".to_string()),
+            _ => {
+                let c = char::from(b'A' + g as u8);
+                (format!("{c}{c}A"), format!("{c}{c}Z"))
+            }
+        };
+
+        // The loop visits n nodes, the last being the Z node.
+        let n = len * k;
+        let pairs: Vec<(String, String)> = (0..n - 1)
+            .map(|_| {
+                next_name += 2;
+                (name(next_name - 2), name(next_name - 1))
+            })
+            .collect();
+        let exits = |i: usize| match i == n - 1 {
+            true => format!("({}, {})", end, end),
+            false => format!("({}, {})", pairs[i].0, pairs[i].1),
+        };
 
-        // println!("{}, {}, {}", name, left, right);
-        map.insert(name, node);
+        lines.push(format!("{} = {}", start, exits(0)));
+        for (i, (left, right)) in pairs.iter().enumerate() {
+            lines.push(format!("{} = {}", left, exits(i + 1)));
+            lines.push(format!("{} = {}", right, exits(i + 1)));
+        }
+        lines.push(format!("{} = {}", end, exits(0)));
     }
+    rng.shuffle(&mut lines);
 
-    Input { instructions, map }
+    format!("{}\n\n{}\n", instructions, lines.join("\n"))
 }
 
 #[allow(unused_variables)]
@@ -88,46 +166,148 @@ pub fn part1(input: &Input) -> Option<u64> {
     Some(steps)
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &Input) -> Option<u64> {
-    let mut nodes = input.map.values().filter(|&node| node.a_node);
-    let num_nodes = nodes.clone().count();
+// How a single ghost moves through the network once its (node, instruction)
+// state starts repeating. Z hits are step counts from the ghost's start.
+#[derive(Debug)]
+pub struct GhostCycle {
+    tail: u64,
+    period: u64,
+    tail_hits: Vec<u64>,
+    cycle_hits: Vec<u64>,
+}
+
+impl GhostCycle {
+    pub fn is_hit(&self, step: u64) -> bool {
+        if step < self.tail {
+            return self.tail_hits.contains(&step);
+        }
+        let step = self.tail + (step - self.tail) % self.period;
+        self.cycle_hits.contains(&step)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Method {
+    Lcm,
+    Crt,
+}
+
+pub fn ghost_cycle(input: &Input, start: &str) -> Option<GhostCycle> {
     let m = input.instructions.len();
+    let mut seen = HashMap::new();
+    let mut hits = Vec::new();
+    let mut steps: u64 = 0;
+    let mut i = 0;
+    let mut next = start;
+    loop {
+        let node = input.map.get(next)?;
+        if let Some(&tail) = seen.get(&(next, i)) {
+            let (tail_hits, cycle_hits) = hits.iter().partition(|&&hit| hit < tail);
+            return Some(GhostCycle {
+                tail,
+                period: steps - tail,
+                tail_hits,
+                cycle_hits,
+            });
+        }
+        seen.insert((next, i), steps);
+        if node.z_node {
+            hits.push(steps);
+        }
 
-    let mut cycles = Vec::new();
-    for node in nodes {
-        let mut steps: u64 = 0;
-        let mut i = 0;
-        let mut next = &node.name;
-        while let Some(node) = &input.map.get(next) {
-            if node.z_node {
-                cycles.push(steps);
-                break;
-            }
+        let instruction = &input.instructions[i];
+        next = match instruction {
+            Direction::Left => &node.left,
+            Direction::Right => &node.right,
+        };
 
-            let instruction = &input.instructions[i];
-            next = match instruction {
-                Direction::Left => &node.left,
-                Direction::Right => &node.right,
-            };
+        steps += 1;
+        i = (i + 1) % m;
+    }
+}
 
-            steps += 1;
-            i = (i + 1) % m;
+// Combines t = r1 (mod m1) and t = r2 (mod m2) into a single congruence, if
+// one exists. The moduli don't need to be coprime.
+fn crt((r1, m1): (i128, i128), (r2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let ExtendedGcd { gcd, x, .. } = m1.extended_gcd(&m2);
+    if (r2 - r1) % gcd != 0 {
+        return None;
+    }
+
+    let m = m1 / gcd * m2;
+    let k = ((r2 - r1) / gcd * x).rem_euclid(m2 / gcd);
+    Some(((r1 + m1 * k).rem_euclid(m), m))
+}
+
+// Finds the first step at which every ghost is on a Z node. When each ghost
+// loops back to its only Z node exactly once per period the answer is just
+// the LCM of the periods, otherwise every combination of Z offsets is solved
+// with the generalised CRT.
+pub fn solve_ghosts(input: &Input) -> Option<(u64, Method)> {
+    let cycles = input
+        .map
+        .values()
+        .filter(|&node| node.a_node)
+        .map(|node| ghost_cycle(input, &node.name))
+        .collect::<Option<Vec<_>>>()?;
+
+    let simple = cycles
+        .iter()
+        .all(|c| c.tail_hits.is_empty() && c.cycle_hits == [c.period]);
+    if simple {
+        let lcm = cycles.iter().fold(1, |acc, c| lcm(acc, c.period));
+        return Some((lcm, Method::Lcm));
+    }
+
+    // A hit before some ghost has entered its cycle is always earlier than
+    // anything the congruences can produce.
+    let mut tail_hits: Vec<u64> = cycles.iter().flat_map(|c| c.tail_hits.clone()).collect();
+    tail_hits.sort();
+    if let Some(&step) = tail_hits
+        .iter()
+        .find(|&&step| cycles.iter().all(|c| c.is_hit(step)))
+    {
+        return Some((step, Method::Crt));
+    }
+
+    let min_step = cycles.iter().map(|c| c.tail).max()? as i128;
+    let mut best: Option<u64> = None;
+    let mut combos = vec![(0, 1)];
+    for cycle in &cycles {
+        let mut next_combos = Vec::new();
+        for &combo in &combos {
+            for &hit in &cycle.cycle_hits {
+                if let Some(combined) = crt(combo, (hit as i128, cycle.period as i128)) {
+                    next_combos.push(combined);
+                }
+            }
         }
+        combos = next_combos;
+    }
+
+    for (r, m) in combos {
+        // Smallest step >= min_step with step = r (mod m).
+        let step = r + Integer::div_ceil(&(min_step - r), &m).max(0) * m;
+        let step = step as u64;
+        best = Some(best.map_or(step, |b| u64::min(b, step)));
     }
 
-    let lcm = cycles.iter().fold(1, |acc, v| lcm(acc, *v));
+    best.map(|step| (step, Method::Crt))
+}
 
-    Some(lcm)
+#[allow(unused_variables)]
+pub fn part2(input: &Input) -> Option<u64> {
+    solve_ghosts(input).map(|(steps, _)| steps)
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day08/test.txt");
     #[test]
     fn test_day08_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -137,10 +317,39 @@ pub mod tests {
     const TEST_INPUT2: &str = include_str!("../../input/day08/test2.txt");
     #[test]
     fn test_day08_part2() {
-        let input = parse_input(TEST_INPUT2);
+        let input = parse_input(TEST_INPUT2).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(6));
     }
+
+    #[test]
+    fn test_day08_solve_ghosts() {
+        let input = parse_input(TEST_INPUT2).unwrap();
+        assert_eq!(solve_ghosts(&input), Some((6, Method::Crt)));
+
+        // AAA hits a Z on every even step, BBA one step after each multiple
+        // of three. Taking the first hits and LCMing them would give 2.
+        let input = parse_input(
+            "L\n\nAAA = (AAB, AAB)\nAAB = (AAZ, AAZ)\nAAZ = (AAB, AAB)\n\
+             BBA = (BBZ, BBZ)\nBBZ = (BBB, BBB)\nBBB = (BBC, BBC)\nBBC = (BBZ, BBZ)",
+        )
+        .unwrap();
+        assert_eq!(solve_ghosts(&input), Some((4, Method::Crt)));
+
+        let input = parse_input(include_str!("../../input/day08/input.txt")).unwrap();
+        assert!(matches!(solve_ghosts(&input), Some((_, Method::Lcm))));
+    }
+
+    #[test]
+    fn test_day08_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+
+        assert_eq!(
+            parse_input("\n\nAAA = (ZZZ, ZZZ)\nZZZ = (ZZZ, ZZZ)\n").err(),
+            Some(ParseError::new(1, "expected L or R"))
+        );
+    }
 }
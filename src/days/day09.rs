@@ -1,69 +1,164 @@
-pub fn parse_input(input: &str) -> Vec<Vec<i64>> {
-    input
-        .lines()
-        .map(|line| line.split(' ').map(|s| s.parse().unwrap()).collect())
-        .collect()
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    rng::Rng,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    NotPolynomial,
+    // The value, or a step on the way to it, doesn't fit.
+    Overflow,
 }
 
-#[allow(unused_variables)]
-pub fn part1(input: &[Vec<i64>]) -> Option<u64> {
-    let mut sum = 0;
-    for nums in input {
-        let mut saved: Vec<i64> = Vec::new();
-        let mut current = nums.clone();
-        let mut iteration = 0;
-        let mut running = true;
-        while running {
-            saved.push(current[current.len() - 1 - iteration]);
-            for i in 0..=current.len() - 2 - iteration {
-                current[i] = current[i + 1] - current[i];
-            }
+// A sequence sampled at 0, 1, 2, ... stored as its Newton forward differences
+// f(0), Δf(0), Δ²f(0), ...
+#[derive(Debug)]
+pub struct Sequence {
+    len: usize,
+    diffs: Vec<i64>,
+    degree: Option<usize>,
+}
+
+impl Sequence {
+    pub fn new(values: &[i64]) -> Sequence {
+        let mut current = values.to_vec();
+        let mut diffs = Vec::new();
+        while !current.is_empty() {
+            diffs.push(current[0]);
+            current = current.windows(2).map(|w| w[1] - w[0]).collect();
+        }
+
+        // The degree is only known if at least one all-zero row of
+        // differences remains to confirm it.
+        let nonzero = diffs.iter().rposition(|&d| d != 0).unwrap_or(0);
+        let degree = match nonzero + 1 < diffs.len() {
+            true => Some(nonzero),
+            false => None,
+        };
+
+        Sequence {
+            len: values.len(),
+            diffs,
+            degree,
+        }
+    }
 
-            let slice = &current[0..current.len() - 1 - iteration];
-            if !slice.iter().any(|&num| num != 0) {
-                running = false;
+    pub fn degree(&self) -> Result<usize, Error> {
+        self.degree.ok_or(Error::NotPolynomial)
+    }
+
+    // f(n) = Σ C(n, j) Δʲf(0), which holds for any integer n including
+    // negative ones.
+    pub fn at(&self, n: i64) -> Result<i64, Error> {
+        let degree = self.degree()?;
+        let n = n as i128;
+
+        let mut sum: i128 = 0;
+        let mut binom: i128 = 1;
+        for (j, &diff) in self.diffs[..=degree].iter().enumerate() {
+            if j > 0 {
+                binom = binom
+                    .checked_mul(n - j as i128 + 1)
+                    .ok_or(Error::Overflow)?
+                    / j as i128;
             }
-            iteration += 1;
+            let term = binom.checked_mul(diff as i128).ok_or(Error::Overflow)?;
+            sum = sum.checked_add(term).ok_or(Error::Overflow)?;
         }
-        sum += saved.iter().sum::<i64>();
+
+        i64::try_from(sum).map_err(|_| Error::Overflow)
     }
 
-    Some(sum as u64)
+    // Value k steps past the last term, counting back from it when k is
+    // negative.
+    pub fn extrapolate(&self, k: i64) -> Result<i64, Error> {
+        let n = (self.len as i64 - 1)
+            .checked_add(k)
+            .ok_or(Error::Overflow)?;
+        self.at(n)
+    }
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &[Vec<i64>]) -> Option<u64> {
-    let mut sum = 0;
-    for nums in input {
-        let mut saved: Vec<i64> = Vec::new();
-        let mut current = nums.clone();
-        let mut iteration = 0;
-        let mut running = true;
-        while running {
-            saved.push(current[0]);
-            for i in 0..=current.len() - 2 - iteration {
-                current[i] = current[i + 1] - current[i];
+// Whether every row of differences still fits in an i64.
+fn differences_fit(values: &[i64]) -> bool {
+    let mut current = values.to_vec();
+    while current.len() > 1 {
+        let next: Option<Vec<i64>> = current.windows(2).map(|w| w[1].checked_sub(w[0])).collect();
+        match next {
+            Some(next) => current = next,
+            None => return false,
+        }
+    }
+
+    true
+}
+
+pub fn parse_input(input: &str) -> Result<Vec<Sequence>, ParseError> {
+    numbered_lines(input)
+        .map(|(n, line)| {
+            let nums: Vec<i64> = line
+                .split(' ')
+                .map(|s| number(n, s))
+                .collect::<Result<_, _>>()?;
+            if !differences_fit(&nums) {
+                return Err(ParseError::new(n, "differences too large"));
             }
+            Ok(Sequence::new(&nums))
+        })
+        .collect()
+}
 
-            let slice = &current[0..current.len() - 1 - iteration];
-            if !slice.iter().any(|&num| num != 0) {
-                running = false;
+// Rows of 21 values taken from random polynomials of degree up to 6, built up
+// from their forward differences. Those are all small except the first value,
+// and the rest are never negative, so both extrapolations stay positive.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let mut out = String::new();
+    for _ in 0..size {
+        let degree = rng.below(7);
+        let mut diffs: Vec<i64> = (0..=degree).map(|_| rng.range(0..11)).collect();
+        diffs[0] = rng.range(100..1000);
+        let mut values = Vec::new();
+        for _ in 0..21 {
+            values.push(diffs[0].to_string());
+            for i in 0..degree {
+                diffs[i] += diffs[i + 1];
             }
-            iteration += 1;
         }
-        sum += saved.iter().rev().fold(0, |acc, v| v - acc);
+        out.push_str(&values.join(" "));
+        out.push('\n');
     }
 
-    Some(sum as u64)
+    out
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &[Sequence]) -> Option<u64> {
+    let sum = input
+        .iter()
+        .map(|seq| seq.extrapolate(1).ok())
+        .try_fold(0i64, |sum, value| sum.checked_add(value?))?;
+
+    u64::try_from(sum).ok()
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &[Sequence]) -> Option<u64> {
+    let sum = input
+        .iter()
+        .map(|seq| seq.at(-1).ok())
+        .try_fold(0i64, |sum, value| sum.checked_add(value?))?;
+
+    u64::try_from(sum).ok()
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day09/test.txt");
     #[test]
     fn test_day09_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -72,10 +167,67 @@ pub mod tests {
 
     #[test]
     fn test_day09_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(2));
     }
+
+    #[test]
+    fn test_day09_sequence() {
+        let seq = Sequence::new(&[10, 13, 16, 21, 30, 45]);
+        assert_eq!(seq.degree(), Ok(3));
+        assert_eq!(seq.extrapolate(1), Ok(68));
+        assert_eq!(seq.extrapolate(0), Ok(45));
+        assert_eq!(seq.extrapolate(-1), Ok(30));
+        assert_eq!(seq.extrapolate(-5), Ok(10));
+        assert_eq!(seq.extrapolate(-6), Ok(5));
+        assert_eq!(seq.at(0), Ok(10));
+        assert_eq!(seq.at(-1), Ok(5));
+
+        // n² - 3n, far outside the sampled range in both directions.
+        let seq = Sequence::new(&[0, -2, -2, 0]);
+        assert_eq!(seq.degree(), Ok(2));
+        assert_eq!(seq.at(1000), Ok(997000));
+        assert_eq!(seq.at(-1000), Ok(1003000));
+        assert_eq!(seq.extrapolate(-1003), Ok(1003000));
+
+        assert_eq!(Sequence::new(&[7, 7]).degree(), Ok(0));
+        assert_eq!(
+            Sequence::new(&[1, 2, 4, 8]).degree(),
+            Err(Error::NotPolynomial)
+        );
+        assert_eq!(
+            Sequence::new(&[1, 2, 4, 8]).extrapolate(1),
+            Err(Error::NotPolynomial)
+        );
+        assert_eq!(Sequence::new(&[]).degree(), Err(Error::NotPolynomial));
+    }
+
+    #[test]
+    fn test_day09_overflow() {
+        // C(n, 20), which is 0 until n = 20 and then grows fast either way.
+        let mut values = vec![0; 20];
+        values.extend([1, 21]);
+        let seq = Sequence::new(&values);
+        assert_eq!(seq.degree(), Ok(20));
+        assert_eq!(seq.at(40), Ok(137846528820));
+        assert_eq!(seq.at(-1), Ok(1));
+        assert_eq!(seq.at(1000), Err(Error::Overflow));
+        assert_eq!(seq.extrapolate(-1_000_000), Err(Error::Overflow));
+        assert_eq!(seq.at(i64::MIN), Err(Error::Overflow));
+        assert_eq!(seq.extrapolate(i64::MAX), Err(Error::Overflow));
+
+        // Every intermediate fits, but n² itself is past i64::MAX.
+        let seq = Sequence::new(&[0, 1, 4, 9]);
+        assert_eq!(seq.at(3037000499), Ok(9223372030926249001));
+        assert_eq!(seq.at(3037000500), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn test_day09_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
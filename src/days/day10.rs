@@ -1,4 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    polygon::{self, Location},
+    rng::Rng,
+};
 
 type Point = (i32, i32);
 
@@ -19,6 +23,19 @@ pub enum Token {
 }
 
 impl Token {
+    pub fn as_box_char(&self) -> char {
+        match self {
+            Token::Vertical => '│',
+            Token::Horizontal => '─',
+            Token::NorthEast => '└',
+            Token::NorthWest => '┘',
+            Token::SouthWest => '┐',
+            Token::SouthEast => '┌',
+            Token::Ground => ' ',
+            Token::Start => 'S',
+        }
+    }
+
     pub fn as_directions(&self) -> (Point, Point) {
         match self {
             Token::NorthEast => ((1, 0), (0, -1)),
@@ -42,7 +59,7 @@ pub struct Input {
 
 impl Input {
     pub fn at(&self, x: i32, y: i32) -> &Token {
-        if x < 0 || x >= self.width || y < 0 || y > self.height {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
             return &Token::Ground;
         }
         &self.tokens[y as usize][x as usize]
@@ -51,17 +68,18 @@ impl Input {
     pub fn at_move(&self, p1: Point, p2: Point) -> &Token {
         let x = p1.0 + p2.0;
         let y = p1.1 + p2.1;
-        if x < 0 || x >= self.width || y < 0 || y > self.height {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
             return &Token::Ground;
         }
         &self.tokens[y as usize][x as usize]
     }
 }
 
-pub fn parse_input(input: &str) -> Input {
-    let mut vecs = Vec::new();
-    let mut start = (0, 0);
-    for (i, line) in input.lines().enumerate() {
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
+    let mut vecs: Vec<Vec<Token>> = Vec::new();
+    let mut start = None;
+    for (n, line) in numbered_lines(input) {
+        let i = n - 1;
         let mut tokens = Vec::new();
         for (j, c) in line.chars().enumerate() {
             let token = match c {
@@ -72,35 +90,53 @@ pub fn parse_input(input: &str) -> Input {
                 '7' => Token::SouthWest,
                 'F' => Token::SouthEast,
                 '.' => Token::Ground,
+                'S' if start.is_some() => return Err(ParseError::new(n, "second start tile")),
                 'S' => {
-                    start = (j as i32, i as i32);
+                    start = Some((j as i32, i as i32));
                     Token::Start
                 }
-                _ => {
-                    println!("{}", c);
-                    unreachable!();
-                }
+                _ => return Err(ParseError::new(n, "unknown tile")),
             };
             tokens.push(token);
         }
+        if vecs
+            .first()
+            .is_some_and(|first| first.len() != tokens.len())
+        {
+            return Err(ParseError::new(n, "rows differ in length"));
+        }
         vecs.push(tokens);
     }
 
+    let start = start.ok_or(ParseError::new(0, "no start tile"))?;
     let width = vecs[0].len() as i32;
     let height = vecs.len() as i32;
 
-    Input {
+    let input = Input {
         tokens: vecs,
         width,
         height,
         start,
+    };
+
+    let line = start.1 as usize + 1;
+    if start_token(&input).is_none() {
+        return Err(ParseError::new(
+            line,
+            "start doesn't join exactly two pipes",
+        ));
+    }
+    if walk_loop(&input).is_none() {
+        return Err(ParseError::new(line, "loop doesn't lead back to the start"));
     }
-}
 
-#[allow(unused_variables)]
-pub fn part1(input: &Input) -> Option<u64> {
-    let mut curr_pos = input.start;
+    Ok(input)
+}
 
+// The pipe hidden under the start tile, worked out from its neighbours. None
+// unless exactly two of them join it.
+pub fn start_token(input: &Input) -> Option<&'static Token> {
+    let curr_pos = input.start;
     let upper = input.at(curr_pos.0, curr_pos.1 - 1);
     let lower = input.at(curr_pos.0, curr_pos.1 + 1);
     let right = input.at(curr_pos.0 + 1, curr_pos.1);
@@ -111,30 +147,42 @@ pub fn part1(input: &Input) -> Option<u64> {
     let east_conn = [Token::Horizontal, Token::SouthWest, Token::NorthWest].contains(right);
     let west_conn = [Token::Horizontal, Token::SouthEast, Token::NorthEast].contains(left);
 
-    let mut curr_token = match (north_conn, east_conn, south_conn, west_conn) {
+    let token = match (north_conn, east_conn, south_conn, west_conn) {
         (true, true, false, false) => &Token::NorthEast,
         (true, false, true, false) => &Token::Vertical,
         (true, false, false, true) => &Token::NorthWest,
         (false, true, true, false) => &Token::SouthEast,
         (false, true, false, true) => &Token::Horizontal,
         (false, false, true, true) => &Token::SouthWest,
-        _ => unreachable!(),
+        _ => return None,
     };
 
+    Some(token)
+}
+
+// Walks the loop from the start tile, returning every tile on it in order.
+// None if the pipes lead somewhere that doesn't connect back.
+fn walk_loop(input: &Input) -> Option<Vec<Point>> {
+    let mut curr_pos = input.start;
+    let mut loop_points = Vec::new();
+    let mut curr_token = start_token(input)?;
+
     let mut next_move = curr_token.as_directions().0;
     let mut next_pos = (curr_pos.0 + next_move.0, curr_pos.1 + next_move.1);
 
-    let mut steps = 0;
     while curr_token != &Token::Start {
         let next_token = input.at(next_pos.0, next_pos.1);
-        steps += 1;
+        loop_points.push(curr_pos);
 
         let possible_moves = next_token.as_directions();
+        let back = (-next_move.0, -next_move.1);
 
-        if (-next_move.0, -next_move.1) == possible_moves.0 {
+        if back == possible_moves.0 {
             next_move = possible_moves.1;
-        } else {
+        } else if back == possible_moves.1 {
             next_move = possible_moves.0;
+        } else if next_token != &Token::Start {
+            return None;
         }
 
         curr_pos = next_pos;
@@ -142,103 +190,220 @@ pub fn part1(input: &Input) -> Option<u64> {
         next_pos = (curr_pos.0 + next_move.0, curr_pos.1 + next_move.1);
     }
 
-    Some(steps >> 1)
+    Some(loop_points)
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &Input) -> Option<u64> {
-    let mut curr_pos = input.start;
-    let mut loop_points = HashSet::new();
-
-    let upper = input.at(curr_pos.0, curr_pos.1 - 1);
-    let lower = input.at(curr_pos.0, curr_pos.1 + 1);
-    let right = input.at(curr_pos.0 + 1, curr_pos.1);
-    let left = input.at(curr_pos.0 - 1, curr_pos.1);
+// The loop through the start tile. `parse_input` only accepts inputs where
+// there is one.
+pub fn find_loop(input: &Input) -> Vec<Point> {
+    walk_loop(input).unwrap_or_default()
+}
 
-    let north_conn = [Token::Vertical, Token::SouthWest, Token::SouthEast].contains(upper);
-    let south_conn = [Token::Vertical, Token::NorthWest, Token::NorthEast].contains(lower);
-    let east_conn = [Token::Horizontal, Token::SouthWest, Token::NorthWest].contains(right);
-    let west_conn = [Token::Horizontal, Token::SouthEast, Token::NorthEast].contains(left);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Tile {
+    Loop,
+    Inside,
+    Outside,
+}
 
-    let mut start_token = match (north_conn, east_conn, south_conn, west_conn) {
-        (true, true, false, false) => &Token::NorthEast,
-        (true, false, true, false) => &Token::Vertical,
-        (true, false, false, true) => &Token::NorthWest,
-        (false, true, true, false) => &Token::SouthEast,
-        (false, true, false, true) => &Token::Horizontal,
-        (false, false, true, true) => &Token::SouthWest,
-        _ => unreachable!(),
-    };
+// Sorts every tile into the loop itself and what it encloses.
+pub fn classify(input: &Input) -> Vec<Vec<Tile>> {
+    let loop_points = find_loop(input);
+    let mut tiles = vec![vec![Tile::Outside; input.width as usize]; input.height as usize];
+    for &(x, y) in &loop_points {
+        tiles[y as usize][x as usize] = Tile::Loop;
+    }
 
-    let mut curr_token = start_token;
-    let mut next_move = curr_token.as_directions().0;
-    let mut next_pos = (curr_pos.0 + next_move.0, curr_pos.1 + next_move.1);
+    // Only the corners are needed for the point-in-polygon test.
+    let n = loop_points.len();
+    let corners: Vec<polygon::Point> = (0..n)
+        .filter(|&i| {
+            let (prev, curr, next) = (
+                loop_points[(i + n - 1) % n],
+                loop_points[i],
+                loop_points[(i + 1) % n],
+            );
+            (curr.0 - prev.0, curr.1 - prev.1) != (next.0 - curr.0, next.1 - curr.1)
+        })
+        .map(|i| (loop_points[i].0 as i64, loop_points[i].1 as i64))
+        .collect();
+
+    for (y, row) in tiles.iter_mut().enumerate() {
+        for (x, tile) in row.iter_mut().enumerate() {
+            if *tile == Tile::Loop {
+                continue;
+            }
+            if polygon::locate_rectilinear(&corners, (x as i64, y as i64)) == Location::Inside {
+                *tile = Tile::Inside;
+            }
+        }
+    }
 
-    while curr_token != &Token::Start {
-        let next_token = input.at(next_pos.0, next_pos.1);
-        loop_points.insert(curr_pos);
+    tiles
+}
 
-        let possible_moves = next_token.as_directions();
+fn token_at(input: &Input, x: usize, y: usize) -> &Token {
+    match &input.tokens[y][x] {
+        Token::Start => start_token(input).unwrap_or(&Token::Start),
+        token => token,
+    }
+}
 
-        if (-next_move.0, -next_move.1) == possible_moves.0 {
-            next_move = possible_moves.1;
-        } else {
-            next_move = possible_moves.0;
+// Draws the loop with box-drawing characters, every other pipe dimmed, and
+// enclosed tiles as an inverse-video 'I'.
+pub fn render(input: &Input) -> String {
+    let tiles = classify(input);
+    let mut out = String::new();
+    for (y, row) in tiles.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let c = token_at(input, x, y).as_box_char();
+            match tile {
+                Tile::Loop => out.push(c),
+                Tile::Inside => out.push_str("\x1b[7mI\x1b[0m"),
+                Tile::Outside if c == ' ' => out.push(c),
+                Tile::Outside => out.push_str(&format!("\x1b[2m{}\x1b[0m", c)),
+            }
         }
-
-        curr_pos = next_pos;
-        curr_token = next_token;
-        next_pos = (curr_pos.0 + next_move.0, curr_pos.1 + next_move.1);
+        out.push('\n');
     }
 
-    let mut count = 0;
-    for (y, line) in input.tokens.iter().enumerate() {
-        let mut inside = false;
-        let mut open_north = false;
-        let mut it = line.iter();
-        let mut x = 0;
-        while let Some(mut token) = it.next() {
-            if !loop_points.contains(&(x, y as i32)) {
-                if inside {
-                    count += 1;
-                }
-                x += 1;
-                continue;
-            }
-            if token == &Token::Start {
-                token = start_token;
-            }
+    out
+}
 
-            x += 1;
-            match *token {
-                Token::Vertical => inside = !inside,
-                Token::SouthEast => open_north = false,
-                Token::NorthEast => open_north = true,
-                Token::SouthWest => {
-                    if open_north {
-                        inside = !inside;
-                    }
-                }
-                Token::NorthWest => {
-                    if !open_north {
-                        inside = !inside;
-                    }
+// Binary PPM image with each tile drawn as a 3x3 block: loop pipes in white,
+// other pipes in grey and enclosed tiles filled green.
+pub fn to_ppm(input: &Input) -> Vec<u8> {
+    let tiles = classify(input);
+    let (width, height) = (3 * input.width as usize, 3 * input.height as usize);
+    let mut pixels = vec![[0u8; 3]; width * height];
+    for (y, row) in tiles.iter().enumerate() {
+        for (x, tile) in row.iter().enumerate() {
+            let token = token_at(input, x, y);
+            let cells: Vec<Point> = match (tile, token) {
+                (Tile::Inside, _) => (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .collect(),
+                (_, Token::Ground) => continue,
+                _ => {
+                    let (a, b) = token.as_directions();
+                    vec![(0, 0), a, b]
                 }
-                _ => continue,
+            };
+            let colour = match tile {
+                Tile::Loop => [255, 255, 255],
+                Tile::Inside => [40, 160, 40],
+                Tile::Outside => [90, 90, 90],
+            };
+
+            for (dx, dy) in cells {
+                let px = (3 * x as i32 + 1 + dx) as usize;
+                let py = (3 * y as i32 + 1 + dy) as usize;
+                pixels[py * width + px] = colour;
             }
         }
     }
 
-    Some(count)
+    let mut out = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    out.extend(pixels.iter().flatten());
+    out
+}
+
+// A square field whose loop is a random skyline around the start row,
+// surrounded by junk pipes. The start's neighbours off the loop are cleared
+// so there's only one way to read its pipe.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let size = size.max(6) as i32;
+    let mid = size / 2;
+
+    // The loop spans columns 1 to size - 2, and rows 1 to size - 2 with the
+    // start row in the middle.
+    let height = (size - mid - 2) as i64;
+    let width = (size - 3) as i64;
+    let count = match height {
+        1 => 1,
+        _ => 1 + rng.below(width as usize / 2),
+    };
+    let corners: Vec<Point> = polygon::random_skyline(rng, count, width, height)
+        .iter()
+        .map(|&(x, y)| (1 + x as i32, mid - y as i32))
+        .collect();
+
+    let mut path: Vec<Point> = Vec::new();
+    for (i, &(x1, y1)) in corners.iter().enumerate() {
+        let (x2, y2) = corners[(i + 1) % corners.len()];
+        let (dx, dy) = ((x2 - x1).signum(), (y2 - y1).signum());
+        let mut p = (x1, y1);
+        while p != (x2, y2) {
+            path.push(p);
+            p = add_points(p, (dx, dy));
+        }
+    }
+
+    let mut grid: Vec<Vec<char>> = (0..size)
+        .map(|_| {
+            (0..size)
+                .map(|_| *rng.choose(&['|', '-', 'L', 'J', '7', 'F', '.']))
+                .collect()
+        })
+        .collect();
+    // Drawn before the loop, which may run right next to the start.
+    grid[mid as usize][0] = '.';
+    grid[mid as usize][2] = '.';
+
+    let n = path.len();
+    for (i, &(x, y)) in path.iter().enumerate() {
+        let prev = path[(i + n - 1) % n];
+        let next = path[(i + 1) % n];
+        let mut dirs = [(prev.0 - x, prev.1 - y), (next.0 - x, next.1 - y)];
+        dirs.sort();
+        grid[y as usize][x as usize] = match dirs {
+            [(0, -1), (0, 1)] => '|',
+            [(-1, 0), (1, 0)] => '-',
+            [(0, -1), (1, 0)] => 'L',
+            [(-1, 0), (0, -1)] => 'J',
+            [(-1, 0), (0, 1)] => '7',
+            [(0, 1), (1, 0)] => 'F',
+            _ => unreachable!(),
+        };
+    }
+    grid[mid as usize][1] = 'S';
+
+    let mut out = String::new();
+    for row in grid {
+        out.extend(row);
+        out.push('\n');
+    }
+
+    out
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &Input) -> Option<u64> {
+    let steps = find_loop(input).len() as u64;
+
+    Some(steps >> 1)
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &Input) -> Option<u64> {
+    // The loop passes through tile centres, so enclosed tiles are exactly the
+    // lattice points strictly inside it.
+    let loop_points: Vec<polygon::Point> = find_loop(input)
+        .iter()
+        .map(|&(x, y)| (x as i64, y as i64))
+        .collect();
+
+    polygon::interior_points(&loop_points)
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day10/test.txt");
     #[test]
     fn test_day10_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -248,10 +413,51 @@ pub mod tests {
     const TEST_INPUT2: &str = include_str!("../../input/day10/test2.txt");
     #[test]
     fn test_day10_part2() {
-        let input = parse_input(TEST_INPUT2);
+        let input = parse_input(TEST_INPUT2).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(10));
     }
+
+    #[test]
+    fn test_day10_broken_loops() {
+        assert_eq!(
+            parse_input("S-.\n...").err(),
+            Some(ParseError::new(1, "start doesn't join exactly two pipes"))
+        );
+        assert_eq!(
+            parse_input("F-7\n|..\nS-J").err(),
+            Some(ParseError::new(3, "loop doesn't lead back to the start"))
+        );
+    }
+
+    #[test]
+    fn test_day10_render() {
+        let input = parse_input(TEST_INPUT2).unwrap();
+
+        let tiles = classify(&input);
+        let inside = tiles
+            .iter()
+            .flatten()
+            .filter(|&&t| t == Tile::Inside)
+            .count();
+        assert_eq!(Some(inside as u64), part2(&input));
+
+        let rendered = render(&input);
+        let first = rendered.lines().next().unwrap();
+        assert_eq!(first, "\x1b[2m┌\x1b[0m┌┐┌┐┌┐┌┐┌┐┌┐┌┐┌───┐");
+        assert_eq!(rendered.matches("\x1b[7mI\x1b[0m").count(), 10);
+
+        let ppm = to_ppm(&input);
+        let header = b"P6\n60 30\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm.len(), header.len() + 60 * 30 * 3);
+    }
+
+    #[test]
+    fn test_day10_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
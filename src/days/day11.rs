@@ -1,3 +1,7 @@
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
 use std::collections::HashSet;
 
 pub struct Point {
@@ -11,13 +15,17 @@ pub struct Input {
     empty_cols: Vec<bool>,
 }
 
-pub fn parse_input(input: &str) -> Input {
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
     let mut galaxies = Vec::new();
     let mut empty_rows = Vec::new();
     let mut empty_cols = Vec::new();
     let mut width = 0;
     let mut occupied_columns = HashSet::new();
-    for (y, line) in input.lines().enumerate() {
+    for (n, line) in numbered_lines(input) {
+        let y = n - 1;
+        if y > 0 && line.len() != width {
+            return Err(ParseError::new(n, "rows differ in length"));
+        }
         let mut empty_row = true;
         for (x, c) in line.chars().enumerate() {
             width = x + 1;
@@ -31,7 +39,7 @@ pub fn parse_input(input: &str) -> Input {
                     empty_row = false;
                     occupied_columns.insert(x);
                 }
-                _ => unreachable!(),
+                _ => return Err(ParseError::new(n, "expected # or .")),
             }
         }
         if empty_row {
@@ -49,11 +57,73 @@ pub fn parse_input(input: &str) -> Input {
         }
     }
 
-    Input {
+    Ok(Input {
         galaxy_coords: galaxies,
         empty_rows,
         empty_cols,
+    })
+}
+
+// A square image with galaxies scattered everywhere but a few empty rows and
+// columns.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let empty_rows: Vec<bool> = (0..size).map(|_| rng.chance(1, 10)).collect();
+    let empty_cols: Vec<bool> = (0..size).map(|_| rng.chance(1, 10)).collect();
+
+    let mut out = String::new();
+    for &empty_row in &empty_rows {
+        for &empty_col in &empty_cols {
+            match !empty_row && !empty_col && rng.chance(1, 20) {
+                true => out.push('#'),
+                false => out.push('.'),
+            }
+        }
+        out.push('\n');
     }
+
+    out
+}
+
+// Walks from each galaxy to every later one a row and a column at a time,
+// paying `factor` to cross an empty line, for cross-checking the prefix sums.
+#[cfg(test)]
+fn reference_distances(data: &str, factor: u64) -> u64 {
+    let grid: Vec<Vec<char>> = data.lines().map(|line| line.chars().collect()).collect();
+    let empty_row = |y: usize| grid[y].iter().all(|&c| c == '.');
+    let empty_col = |x: usize| grid.iter().all(|row| row[x] == '.');
+
+    let mut galaxies = Vec::new();
+    for (y, row) in grid.iter().enumerate() {
+        for (x, &c) in row.iter().enumerate() {
+            if c == '#' {
+                galaxies.push((x, y));
+            }
+        }
+    }
+
+    let mut sum = 0;
+    for (i, &(x1, y1)) in galaxies.iter().enumerate() {
+        for &(x2, y2) in &galaxies[i + 1..] {
+            for x in x1.min(x2)..x1.max(x2) {
+                sum += if empty_col(x) { factor } else { 1 };
+            }
+            for y in y1.min(y2)..y1.max(y2) {
+                sum += if empty_row(y) { factor } else { 1 };
+            }
+        }
+    }
+
+    sum
+}
+
+#[cfg(test)]
+pub fn reference_part1(data: &str) -> Option<u64> {
+    Some(reference_distances(data, 2))
+}
+
+#[cfg(test)]
+pub fn reference_part2(data: &str) -> Option<u64> {
+    Some(reference_distances(data, 1000000))
 }
 
 #[allow(unused_variables)]
@@ -149,10 +219,12 @@ pub fn part2(input: &Input) -> Option<u64> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
+    use crate::utils::property::assert_agree;
     const TEST_INPUT: &str = include_str!("../../input/day11/test.txt");
     #[test]
     fn test_day11_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -161,10 +233,35 @@ pub mod tests {
 
     #[test]
     fn test_day11_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(82000210));
     }
+
+    #[test]
+    fn test_day11_reference() {
+        assert_eq!(reference_part1(TEST_INPUT), Some(374));
+
+        let generate = |rng: &mut Rng| generate(rng, 15);
+        assert_agree(
+            100,
+            generate,
+            |s| part1(&parse_input(s).unwrap()),
+            reference_part1,
+        );
+        assert_agree(
+            100,
+            generate,
+            |s| part2(&parse_input(s).unwrap()),
+            reference_part2,
+        );
+    }
+
+    #[test]
+    fn test_day11_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
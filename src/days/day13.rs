@@ -1,60 +1,230 @@
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
+
+// A row of cells packed into as many 64 bit words as it needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitRow {
+    words: Vec<u64>,
+}
+
+impl BitRow {
+    pub fn new(len: usize) -> BitRow {
+        BitRow {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    pub fn set(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    // Positions of the cells that differ between the two rows.
+    pub fn diff(&self, other: &BitRow) -> Vec<usize> {
+        let mut positions = Vec::new();
+        for (i, (a, b)) in self.words.iter().zip(&other.words).enumerate() {
+            let mut bits = a ^ b;
+            while bits != 0 {
+                positions.push(64 * i + bits.trailing_zeros() as usize);
+                bits &= bits - 1;
+            }
+        }
+
+        positions
+    }
+
+    // Number of cells that differ between the two rows.
+    pub fn distance(&self, other: &BitRow) -> u32 {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum()
+    }
+}
+
 #[derive(Debug)]
 pub struct Grid {
-    rows: Vec<u64>,
-    cols: Vec<u64>,
+    rows: Vec<BitRow>,
+    cols: Vec<BitRow>,
 }
 
-pub fn parse_cols(rows: &[u64], width: usize) -> Vec<u64> {
+pub fn parse_cols(rows: &[BitRow], width: usize) -> Vec<BitRow> {
     let mut cols = Vec::new();
-    let height = rows.len();
     for i in 0..width {
-        let mask = 1 << (width - 1 - i);
-        let mut val = 0;
+        let mut col = BitRow::new(rows.len());
         for (j, row) in rows.iter().enumerate() {
-            let bit = (mask & row) >> (width - i - 1);
-            let bit = bit << (height - j - 1);
-            val |= bit;
+            if row.get(i) {
+                col.set(j);
+            }
         }
-        cols.push(val);
+        cols.push(col);
     }
 
     cols
 }
 
-pub fn check_reflection(idx: usize, data: &[u64]) -> bool {
-    let length = usize::min(idx + 1, data.len() - idx - 1);
-
-    ((idx + 1 - length)..=idx)
-        .rev()
-        .zip(idx + 1..idx + 1 + length)
-        .all(|(i, j)| data[i] == data[j])
+pub fn check_reflection(idx: usize, data: &[BitRow]) -> bool {
+    check_reflection2(idx, data, 0)
 }
 
-pub fn check_reflection2(idx: usize, data: &[u64]) -> bool {
+// True if mirroring across idx needs exactly `smudges` cells fixed.
+pub fn check_reflection2(idx: usize, data: &[BitRow], smudges: u32) -> bool {
     let length = usize::min(idx + 1, data.len() - idx - 1);
 
-    let mut smudge_rows = 0;
+    let mut smudge_count = 0;
     let reflects = ((idx + 1 - length)..=idx)
         .rev()
         .zip(idx + 1..idx + 1 + length)
         .all(|(i, j)| {
-            let first = data[i];
-            let second = data[j];
-            let one_counts = (first ^ second).count_ones();
-            if one_counts == 1 {
-                smudge_rows += 1;
-            }
-            one_counts <= 1
+            smudge_count += data[i].distance(&data[j]);
+            smudge_count <= smudges
         });
-    reflects && smudge_rows == 1
+    reflects && smudge_count == smudges
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    // Mirror line runs between two rows.
+    Horizontal,
+    // Mirror line runs between two columns.
+    Vertical,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Reflection {
+    pub axis: Axis,
+    // Number of rows above (or columns left of) the mirror line.
+    pub index: usize,
+    // (x, y) of each cell that has to change for the mirror to hold, taken
+    // from the top or left side of the line.
+    pub smudges: Vec<(usize, usize)>,
+}
+
+impl Reflection {
+    pub fn score(&self) -> u64 {
+        match self.axis {
+            Axis::Horizontal => 100 * self.index as u64,
+            Axis::Vertical => self.index as u64,
+        }
+    }
+}
+
+pub fn find_reflection(grid: &Grid, smudges: u32) -> Option<Reflection> {
+    for (axis, data) in [(Axis::Horizontal, &grid.rows), (Axis::Vertical, &grid.cols)] {
+        for idx in 0..data.len() - 1 {
+            if !check_reflection2(idx, data, smudges) {
+                continue;
+            }
+
+            let length = usize::min(idx + 1, data.len() - idx - 1);
+            let mut cells = Vec::new();
+            for i in idx + 1 - length..=idx {
+                for pos in data[i].diff(&data[2 * idx + 1 - i]) {
+                    cells.push(match axis {
+                        Axis::Horizontal => (pos, i),
+                        Axis::Vertical => (i, pos),
+                    });
+                }
+            }
+            cells.sort_by_key(|&(x, y)| (y, x));
+
+            return Some(Reflection {
+                axis,
+                index: idx + 1,
+                smudges: cells,
+            });
+        }
+    }
+
+    None
+}
+
+pub fn score(grid: &Grid, smudges: u32) -> u64 {
+    find_reflection(grid, smudges).map_or(0, |r| r.score())
+}
+
+// Draws the pattern with the mirror line as a row of '-' or a column of '|',
+// and smudged cells in inverse video.
+pub fn render(grid: &Grid, reflection: Option<&Reflection>) -> String {
+    let mut out = String::new();
+    let width = grid.cols.len();
+    for (y, row) in grid.rows.iter().enumerate() {
+        if let Some(Reflection {
+            axis: Axis::Horizontal,
+            index,
+            ..
+        }) = reflection
+        {
+            if *index == y {
+                out.push_str(&"-".repeat(width));
+                out.push('\n');
+            }
+        }
+
+        for x in 0..width {
+            if let Some(Reflection {
+                axis: Axis::Vertical,
+                index,
+                ..
+            }) = reflection
+            {
+                if *index == x {
+                    out.push('|');
+                }
+            }
+
+            let c = match row.get(x) {
+                true => '#',
+                false => '.',
+            };
+            if reflection.is_some_and(|r| r.smudges.contains(&(x, y))) {
+                out.push_str(&format!("\x1b[7m{}\x1b[0m", c));
+            } else {
+                out.push(c);
+            }
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+// Renders every pattern with the reflection found for part 2, falling back to
+// the part 1 one when there's no smudged mirror.
+pub fn render_all(input: &[Grid]) -> String {
+    let mut out = String::new();
+    for (i, grid) in input.iter().enumerate() {
+        let reflection = find_reflection(grid, 1).or_else(|| find_reflection(grid, 0));
+        let title = match &reflection {
+            Some(r) => format!(
+                "{:?} mirror at {}, smudges {:?}",
+                r.axis, r.index, r.smudges
+            ),
+            None => "no mirror".to_string(),
+        };
+        out.push_str(&format!("Pattern {}: {}\n", i + 1, title));
+        out.push_str(&render(grid, reflection.as_ref()));
+        out.push('\n');
+    }
+
+    out
 }
 
-pub fn parse_input(input: &str) -> Vec<Grid> {
+pub fn parse_input(input: &str) -> Result<Vec<Grid>, ParseError> {
     let mut grids = Vec::new();
     let mut rows = Vec::new();
     let mut width = 0;
-    for line in input.lines() {
+    for (n, line) in numbered_lines(input) {
         if line.is_empty() {
+            if rows.is_empty() {
+                return Err(ParseError::new(n, "empty pattern"));
+            }
             let cols = parse_cols(&rows, width);
             let grid = Grid { rows, cols };
             grids.push(grid);
@@ -62,91 +232,95 @@ pub fn parse_input(input: &str) -> Vec<Grid> {
             continue;
         }
 
+        if !rows.is_empty() && line.len() != width {
+            return Err(ParseError::new(n, "rows differ in length"));
+        }
         width = line.len();
-        let mut bit = 1 << (width - 1);
-        let mut val = 0;
-        for c in line.chars() {
-            if let '#' = c {
-                val |= bit
+        let mut row = BitRow::new(width);
+        for (i, c) in line.bytes().enumerate() {
+            match c {
+                b'#' => row.set(i),
+                b'.' => continue,
+                _ => return Err(ParseError::new(n, "expected # or .")),
             }
-            bit >>= 1;
         }
-        rows.push(val);
+        rows.push(row);
+    }
+    if rows.is_empty() {
+        return Err(ParseError::new(0, "missing last pattern"));
     }
     let cols = parse_cols(&rows, width);
     let grid = Grid { rows, cols };
     grids.push(grid);
 
-    grids
+    Ok(grids)
 }
 
-#[allow(unused_variables)]
-pub fn part1(input: &[Grid]) -> Option<u64> {
-    let mut sum = 0;
+// Value at (x, y) once everything past a mirror before `line` is folded back
+// onto the near side.
+fn fold(i: usize, line: usize) -> usize {
+    match i >= line && i < 2 * line {
+        true => 2 * line - 1 - i,
+        false => i,
+    }
+}
 
-    for grid in input {
-        let mut found = false;
+// Patterns with a clean mirror between columns for part 1 and one between rows
+// for part 2 that's off by a single cell. The column mirror sits left of
+// centre, so the flipped cell can be in a column it never reflects. Half the
+// patterns are transposed so either kind of mirror turns up in both parts.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let mut patterns = Vec::new();
+    for _ in 0..size.max(1) {
+        let (w, h) = (rng.range(5..18) as usize, rng.range(5..18) as usize);
+        let a = rng.range(1..((w - 1) / 2) as i64 + 1) as usize;
+        let b = rng.range(1..h as i64) as usize;
 
-        for i in 0..grid.rows.len() - 1 {
-            if check_reflection(i, &grid.rows) {
-                found = true;
-                sum += 100 * (i as u64 + 1);
-                break;
-            }
-        }
+        let base: Vec<Vec<bool>> = (0..h)
+            .map(|_| (0..w).map(|_| rng.chance(1, 2)).collect())
+            .collect();
+        let mut cells: Vec<Vec<bool>> = (0..h)
+            .map(|y| (0..w).map(|x| base[fold(y, b)][fold(x, a)]).collect())
+            .collect();
 
-        if found {
-            continue;
-        }
+        let x = rng.range(2 * a as i64..w as i64) as usize;
+        let y = rng.range((2 * b).saturating_sub(h) as i64..(2 * b).min(h) as i64) as usize;
+        cells[y][x] = !cells[y][x];
 
-        for i in 0..grid.cols.len() - 1 {
-            if check_reflection(i, &grid.cols) {
-                sum += i as u64 + 1;
-                break;
-            }
+        if rng.chance(1, 2) {
+            cells = (0..w)
+                .map(|x| (0..h).map(|y| cells[y][x]).collect())
+                .collect();
         }
+
+        let lines: Vec<String> = cells
+            .iter()
+            .map(|row| row.iter().map(|&c| if c { '#' } else { '.' }).collect())
+            .collect();
+        patterns.push(lines.join("\n"));
     }
 
-    Some(sum)
+    patterns.join("\n\n") + "\n"
 }
 
 #[allow(unused_variables)]
-pub fn part2(input: &[Grid]) -> Option<u64> {
-    let mut sum = 0;
-
-    for grid in input {
-        let mut found = false;
-
-        for i in 0..grid.rows.len() - 1 {
-            if check_reflection2(i, &grid.rows) {
-                found = true;
-                sum += 100 * (i as u64 + 1);
-                break;
-            }
-        }
-
-        if found {
-            continue;
-        }
-
-        for i in 0..grid.cols.len() - 1 {
-            if check_reflection2(i, &grid.cols) {
-                sum += i as u64 + 1;
-                break;
-            }
-        }
-    }
+pub fn part1(input: &[Grid]) -> Option<u64> {
+    Some(input.iter().map(|grid| score(grid, 0)).sum())
+}
 
-    Some(sum)
+#[allow(unused_variables)]
+pub fn part2(input: &[Grid]) -> Option<u64> {
+    Some(input.iter().map(|grid| score(grid, 1)).sum())
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day13/test.txt");
     #[test]
     fn test_day13_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -155,10 +329,70 @@ pub mod tests {
 
     #[test]
     fn test_day13_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(400));
     }
+
+    #[test]
+    fn test_day13_wide_pattern() {
+        // 150 columns mirrored after column 90, so each row spans three
+        // words. No two neighbouring columns match anywhere else.
+        let halves = ["#.#.#.##.##", ".##..##.##.", "...####...#"];
+        let mut lines: Vec<String> = halves
+            .iter()
+            .map(|half| {
+                let left = half.repeat(9)[..90].to_string();
+                let right: String = left.chars().rev().take(60).collect();
+                left + &right
+            })
+            .collect();
+        let input = parse_input(&lines.join("\n")).unwrap();
+        assert_eq!(score(&input[0], 0), 90);
+        assert!(!check_reflection2(89, &input[0].cols, 1));
+
+        for (y, x) in [(1, 70), (2, 140)] {
+            let flipped = match &lines[y][x..x + 1] {
+                "#" => ".",
+                _ => "#",
+            };
+            lines[y].replace_range(x..x + 1, flipped);
+        }
+        let input = parse_input(&lines.join("\n")).unwrap();
+        assert_eq!(score(&input[0], 0), 0);
+        assert!(!check_reflection(89, &input[0].cols));
+        assert!(!check_reflection2(89, &input[0].cols, 1));
+        assert!(check_reflection2(89, &input[0].cols, 2));
+    }
+
+    #[test]
+    fn test_day13_find_reflection() {
+        let input = parse_input(TEST_INPUT).unwrap();
+
+        let reflection = find_reflection(&input[0], 0).unwrap();
+        assert_eq!(reflection.axis, Axis::Vertical);
+        assert_eq!(reflection.index, 5);
+        assert!(reflection.smudges.is_empty());
+
+        let reflection = find_reflection(&input[0], 1).unwrap();
+        assert_eq!(reflection.axis, Axis::Horizontal);
+        assert_eq!(reflection.index, 3);
+        assert_eq!(reflection.smudges, vec![(0, 0)]);
+
+        let reflection = find_reflection(&input[1], 1).unwrap();
+        assert_eq!(reflection.index, 1);
+        assert_eq!(reflection.smudges, vec![(4, 0)]);
+
+        let rendered = render(&input[1], Some(&reflection));
+        assert_eq!(rendered.lines().nth(1), Some("---------"));
+        assert_eq!(rendered.lines().next(), Some("#...\x1b[7m#\x1b[0m#..#"));
+    }
+
+    #[test]
+    fn test_day13_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
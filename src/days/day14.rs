@@ -1,254 +1,347 @@
-use std::{cell::RefCell, collections::HashMap};
+use crate::utils::{
+    cycle::{find_cycle, nth_state},
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
 
-pub fn parse_input(input: &str) -> RefCell<Vec<Vec<u8>>> {
-    let mut bytes = Vec::new();
-    for line in input.lines() {
-        bytes.push(line.as_bytes().to_vec());
-    }
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
 
-    RefCell::new(bytes)
+// The cube rocks never move, so a board is described by its fixed layout plus
+// a set of round rocks. One bit per cell, bit x of row y is column x.
+#[derive(Debug)]
+pub struct Platform {
+    width: usize,
+    height: usize,
+    cubes: Vec<u128>,
+    // (start, end) column ranges between the cube rocks of each row.
+    segments: Vec<Vec<(usize, usize)>>,
+    rounds: Vec<u128>,
 }
 
-// pub fn spin(input: &mut Input) {
-//     // north
-//     for x in 0..input.width {
-//         let mut round_count = 0;
-//         let mut start = 0;
-//         for y in 0..input.height {
-//             let space = input.spaces[y * input.width + x];
-//             match space {
-//                 Space::Round => round_count += 1;
-//                 Space::Empty => continue,
-//                 Space::Square =>
-//
-//             }
-//         }
-//     }
-// }
-
-pub fn shift_north(input: &mut Vec<Vec<u8>>) {
-    for x in 0..input[0].len() {
-        let mut round_count = 0;
-        let mut prev = 0;
-        for y in 0..input.len() {
-            match input[y][x] {
-                b'O' => round_count += 1,
-                b'#' => {
-                    if round_count > 0 {
-                        for j in prev..prev + round_count {
-                            input[j][x] = b'O';
-                        }
-                        for j in prev + round_count..y {
-                            input[j][x] = b'.';
-                        }
-                    }
-                    prev = y + 1;
-                    round_count = 0;
-                }
-                _ => continue,
-            }
-        }
-        if round_count > 0 {
-            for j in prev..prev + round_count {
-                input[j][x] = b'O';
-            }
-            for j in prev + round_count..input.len() {
-                input[j][x] = b'.';
-            }
-        }
+// Bits set for columns start..end.
+fn span(start: usize, end: usize) -> u128 {
+    match end - start {
+        0 => 0,
+        n => (u128::MAX >> (128 - n)) << start,
     }
 }
 
-pub fn shift_west(input: &mut Vec<Vec<u8>>) {
-    for y in 0..input.len() {
-        let mut round_count = 0;
-        let mut prev = 0;
-        for x in 0..input[0].len() {
-            match input[y][x] {
-                b'O' => round_count += 1,
-                b'#' => {
-                    if round_count > 0 {
-                        for j in prev..prev + round_count {
-                            input[y][j] = b'O';
-                        }
-                        for j in prev + round_count..x {
-                            input[y][j] = b'.';
-                        }
-                    }
-                    prev = x + 1;
-                    round_count = 0;
-                }
-                _ => continue,
-            }
-        }
-        if round_count > 0 {
-            for j in prev..prev + round_count {
-                input[y][j] = b'O';
-            }
-            for j in prev + round_count..input[0].len() {
-                input[y][j] = b'.';
-            }
-        }
+impl Platform {
+    fn free(&self, rounds: &[u128], y: usize) -> u128 {
+        !(rounds[y] | self.cubes[y]) & span(0, self.width)
     }
-}
 
-pub fn shift_south(input: &mut Vec<Vec<u8>>) {
-    for x in 0..input[0].len() {
-        let mut round_count = 0;
-        let mut prev = input.len();
-        for y in (0..input.len()).rev() {
-            match input[y][x] {
-                b'O' => round_count += 1,
-                b'#' => {
-                    if round_count > 0 {
-                        for j in y + 1..prev - round_count {
-                            input[j][x] = b'.';
-                        }
-                        for j in prev - round_count..prev {
-                            input[j][x] = b'O';
+    // Vertical tilts carry each row's rocks towards the edge one whole bit
+    // row at a time, settling rows nearest that edge first so every rock
+    // stops in its final place in a single pass. Horizontal tilts count the
+    // rocks between each pair of cubes and pack them against one side.
+    pub fn tilt(&self, rounds: &mut [u128], dir: Direction) {
+        match dir {
+            Direction::North | Direction::South => {
+                let rows: Vec<usize> = match dir {
+                    Direction::North => (0..self.height).collect(),
+                    _ => (0..self.height).rev().collect(),
+                };
+                for (i, &y) in rows.iter().enumerate() {
+                    let mut moving = rounds[y];
+                    rounds[y] = 0;
+                    let mut k = i;
+                    while moving != 0 {
+                        if k == 0 {
+                            rounds[rows[0]] |= moving;
+                            break;
                         }
+                        let can_move = moving & self.free(rounds, rows[k - 1]);
+                        rounds[rows[k]] |= moving & !can_move;
+                        moving = can_move;
+                        k -= 1;
                     }
-                    prev = y;
-                    round_count = 0;
                 }
-                _ => continue,
             }
-        }
-        if round_count > 0 {
-            for j in 0..prev - round_count {
-                input[j][x] = b'.';
-            }
-            for j in prev - round_count..prev {
-                input[j][x] = b'O';
+            Direction::West | Direction::East => {
+                for (row, segments) in rounds.iter_mut().zip(&self.segments) {
+                    let mut tilted = 0;
+                    for &(start, end) in segments {
+                        let count = (*row & span(start, end)).count_ones() as usize;
+                        tilted |= match dir {
+                            Direction::West => span(start, start + count),
+                            _ => span(end - count, end),
+                        };
+                    }
+                    *row = tilted;
+                }
             }
         }
     }
-}
 
-pub fn shift_east(input: &mut Vec<Vec<u8>>) {
-    for y in 0..input.len() {
-        let mut round_count = 0;
-        let mut prev = input[0].len();
-        for x in (0..input[0].len()).rev() {
-            match input[y][x] {
-                b'O' => round_count += 1,
-                b'#' => {
-                    if round_count > 0 {
-                        for j in x + 1..prev - round_count {
-                            input[y][j] = b'.';
-                        }
-                        for j in prev - round_count..prev {
-                            input[y][j] = b'O';
-                        }
-                    }
-                    prev = x;
-                    round_count = 0;
-                }
-                _ => continue,
-            }
+    pub fn spin(&self, rounds: &[u128]) -> Vec<u128> {
+        let mut rounds = rounds.to_vec();
+        for dir in [
+            Direction::North,
+            Direction::West,
+            Direction::South,
+            Direction::East,
+        ] {
+            self.tilt(&mut rounds, dir);
         }
-        if round_count > 0 {
-            for j in 0..prev - round_count {
-                input[y][j] = b'.';
-            }
-            for j in prev - round_count..prev {
-                input[y][j] = b'O';
+
+        rounds
+    }
+
+    pub fn draw(&self, rounds: &[u128]) -> String {
+        let mut out = String::new();
+        for (round, cube) in rounds.iter().zip(&self.cubes) {
+            for x in 0..self.width {
+                out.push(match (round >> x & 1, cube >> x & 1) {
+                    (1, _) => 'O',
+                    (_, 1) => '#',
+                    _ => '.',
+                });
             }
+            out.push('\n');
         }
-    }
-}
 
-#[allow(unused_variables)]
-pub fn part1(input: &RefCell<Vec<Vec<u8>>>) -> Option<u64> {
-    let mut sum = 0;
-    let mut input = input.borrow_mut();
+        out
+    }
 
-    shift_north(&mut input);
+    // Binary PPM image with each cell drawn as a 2x2 block.
+    pub fn to_ppm(&self, rounds: &[u128]) -> Vec<u8> {
+        const SCALE: usize = 2;
 
-    for x in 0..input[0].len() {
-        for y in 0..input.len() {
-            match input[y][x] {
-                b'O' => sum += input.len() - y,
-                _ => continue,
+        let (width, height) = (SCALE * self.width, SCALE * self.height);
+        let mut out = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+        for py in 0..height {
+            let (round, cube) = (rounds[py / SCALE], self.cubes[py / SCALE]);
+            for px in 0..width {
+                let x = px / SCALE;
+                out.extend(match (round >> x & 1, cube >> x & 1) {
+                    (1, _) => [230, 140, 30],
+                    (_, 1) => [120, 120, 120],
+                    _ => [20, 20, 30],
+                });
             }
         }
+
+        out
     }
 
-    Some(sum as u64)
+    pub fn load(&self, rounds: &[u128]) -> u64 {
+        rounds
+            .iter()
+            .enumerate()
+            .map(|(y, row)| row.count_ones() as u64 * (self.height - y) as u64)
+            .sum()
+    }
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &RefCell<Vec<Vec<u8>>>) -> Option<u64> {
-    const END: usize = 1000000000;
-    let mut sum = 0;
-    let mut input = input.borrow_mut();
-
-    let mut state_counts: HashMap<String, i32> = HashMap::new();
-    let mut cycle_length = 0;
-    let mut curr = 0;
-    let mut first_cycle_found = false;
-
-    for i in 0..END {
-        shift_north(&mut input);
-        shift_west(&mut input);
-        shift_south(&mut input);
-        shift_east(&mut input);
-
-        let mut s = String::new();
-        for bytes in input.iter() {
-            s.push_str(&String::from_utf8(bytes.clone()).unwrap());
+pub fn parse_input(input: &str) -> Result<Platform, ParseError> {
+    let mut width = 0;
+    let mut rounds = Vec::new();
+    let mut cubes = Vec::new();
+    for (n, line) in numbered_lines(input) {
+        if !rounds.is_empty() && line.len() != width {
+            return Err(ParseError::new(n, "rows differ in length"));
         }
-        let count = state_counts.entry(s).and_modify(|e| *e += 1).or_insert(1);
-        if *count == 2 && !first_cycle_found {
-            curr = i;
-            first_cycle_found = true;
+        width = line.len();
+        if width > 128 {
+            return Err(ParseError::new(
+                n,
+                "rows wider than 128 cells aren't supported",
+            ));
         }
-        if *count == 3 {
-            cycle_length = i - curr;
-            curr = i;
-            break;
+
+        let mut round = 0;
+        let mut cube = 0;
+        for (x, c) in line.bytes().enumerate() {
+            match c {
+                b'O' => round |= 1 << x,
+                b'#' => cube |= 1 << x,
+                b'.' => continue,
+                _ => return Err(ParseError::new(n, "expected O, # or .")),
+            }
         }
+        rounds.push(round);
+        cubes.push(cube);
     }
 
-    let target = (END - 1 - curr) % cycle_length;
-    let mut curr = 0;
-    while curr != target {
-        shift_north(&mut input);
-        shift_west(&mut input);
-        shift_south(&mut input);
-        shift_east(&mut input);
-        curr = (curr + 1) % cycle_length;
-
-        let mut new_sum = 0;
-        for x in 0..input[0].len() {
-            for y in 0..input.len() {
-                match input[y][x] {
-                    b'O' => new_sum += input.len() - y,
-                    _ => continue,
+    let segments = cubes
+        .iter()
+        .map(|&cube| {
+            let mut segments = Vec::new();
+            let mut start = 0;
+            for x in 0..=width {
+                if x == width || cube & (1 << x) != 0 {
+                    if start < x {
+                        segments.push((start, x));
+                    }
+                    start = x + 1;
                 }
             }
+            segments
+        })
+        .collect();
+
+    Ok(Platform {
+        width,
+        height: rounds.len(),
+        cubes,
+        segments,
+        rounds,
+    })
+}
+
+#[derive(Debug)]
+pub struct Frame {
+    // Spin cycle the frame belongs to, counting from 1. Frame 0 is the
+    // starting board and has no tilt.
+    pub spin: usize,
+    pub tilt: Option<Direction>,
+    pub rounds: Vec<u128>,
+    pub load: u64,
+}
+
+#[derive(Debug)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    // Spins before the boards start repeating, and how many spins a repeat
+    // takes.
+    pub cycle_start: usize,
+    pub period: usize,
+}
+
+impl Frame {
+    pub fn label(&self, animation: &Animation) -> String {
+        let tilt = match self.tilt {
+            Some(dir) => format!("{:?}", dir).to_lowercase(),
+            None => "start".to_string(),
+        };
+        let phase = match self.spin {
+            0 => String::new(),
+            n if n <= animation.cycle_start => ", before cycle".to_string(),
+            n => format!(
+                ", cycle step {}/{}",
+                (n - animation.cycle_start - 1) % animation.period + 1,
+                animation.period
+            ),
+        };
+        format!("spin {} {}, load {}{}", self.spin, tilt, self.load, phase)
+    }
+}
+
+// Every board from the start until the first spin cycle repeats, with one
+// frame after each tilt.
+pub fn animate(input: &Platform) -> Animation {
+    let (cycle_start, period) = find_cycle(&input.rounds, |rounds| input.spin(rounds));
+
+    let mut rounds = input.rounds.clone();
+    let mut frames = vec![Frame {
+        spin: 0,
+        tilt: None,
+        rounds: rounds.clone(),
+        load: input.load(&rounds),
+    }];
+    for spin in 1..=cycle_start + period {
+        for dir in [
+            Direction::North,
+            Direction::West,
+            Direction::South,
+            Direction::East,
+        ] {
+            input.tilt(&mut rounds, dir);
+            frames.push(Frame {
+                spin,
+                tilt: Some(dir),
+                rounds: rounds.clone(),
+                load: input.load(&rounds),
+            });
         }
     }
 
-    for x in 0..input[0].len() {
-        for y in 0..input.len() {
-            match input[y][x] {
-                b'O' => sum += input.len() - y,
-                _ => continue,
-            }
+    Animation {
+        frames,
+        cycle_start,
+        period,
+    }
+}
+
+pub fn render(input: &Platform) -> String {
+    let animation = animate(input);
+    let mut out = format!(
+        "Cycle starts after {} spins with period {}\n\n",
+        animation.cycle_start, animation.period
+    );
+    for frame in &animation.frames {
+        out.push_str(&frame.label(&animation));
+        out.push('\n');
+        out.push_str(&input.draw(&frame.rounds));
+        out.push('\n');
+    }
+
+    out
+}
+
+// (label, image) for every frame of the animation.
+pub fn ppm_frames(input: &Platform) -> Vec<(String, Vec<u8>)> {
+    let animation = animate(input);
+    animation
+        .frames
+        .iter()
+        .map(|frame| (frame.label(&animation), input.to_ppm(&frame.rounds)))
+        .collect()
+}
+
+// Rows are bit masks, so the platform is at most 128 cells wide.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let width = size.clamp(1, 128);
+
+    let mut out = String::new();
+    for _ in 0..size {
+        for _ in 0..width {
+            out.push(match rng.below(8) {
+                0 | 1 => 'O',
+                2 => '#',
+                _ => '.',
+            });
         }
+        out.push('\n');
     }
 
-    Some(sum as u64)
+    out
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &Platform) -> Option<u64> {
+    let mut rounds = input.rounds.clone();
+
+    input.tilt(&mut rounds, Direction::North);
+
+    Some(input.load(&rounds))
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &Platform) -> Option<u64> {
+    const END: usize = 1000000000;
+
+    // Only the round rocks change, so they alone make up the cycle state.
+    let rounds = nth_state(input.rounds.clone(), |rounds| input.spin(rounds), END);
+
+    Some(input.load(&rounds))
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day14/test.txt");
     #[test]
     fn test_day14_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -257,10 +350,46 @@ pub mod tests {
 
     #[test]
     fn test_day14_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(64));
     }
+
+    #[test]
+    fn test_day14_animate() {
+        let input = parse_input(TEST_INPUT).unwrap();
+
+        assert_eq!(input.draw(&input.rounds), TEST_INPUT);
+
+        let animation = animate(&input);
+        assert_eq!((animation.cycle_start, animation.period), (3, 7));
+        assert_eq!(animation.frames.len(), 1 + 4 * 10);
+
+        let first = &animation.frames[1];
+        assert_eq!(first.tilt, Some(Direction::North));
+        assert_eq!(first.load, part1(&input).unwrap());
+        assert_eq!(
+            first.label(&animation),
+            "spin 1 north, load 136, before cycle"
+        );
+        let last = animation.frames.last().unwrap();
+        assert_eq!(
+            last.label(&animation),
+            "spin 10 east, load 69, cycle step 7/7"
+        );
+        assert_eq!(last.rounds, animation.frames[4 * 3].rounds);
+
+        let ppm = input.to_ppm(&last.rounds);
+        let header = b"P6\n20 20\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm.len(), header.len() + 20 * 20 * 3);
+    }
+
+    #[test]
+    fn test_day14_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
@@ -1,5 +1,7 @@
 use std::str;
 
+use crate::utils::{parse::ParseError, rng::Rng};
+
 pub struct MyHashMap<'a> {
     data: Vec<Vec<(&'a str, u8)>>,
 }
@@ -50,8 +52,51 @@ pub fn hash(s: &str) -> u8 {
     curr as u8
 }
 
-pub fn parse_input(input: &str) -> &str {
-    input
+// Checks each step is a label followed by `-` or `=` and a focal length, which
+// is all part 2 knows how to read.
+pub fn parse_input(input: &str) -> Result<&str, ParseError> {
+    if input.trim_end().is_empty() {
+        return Err(ParseError::new(0, "empty input"));
+    }
+    for step in input.trim_end().split(',') {
+        let label_end = step
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(step.len());
+        let valid = match &step.as_bytes()[label_end..] {
+            [b'-'] => true,
+            [b'=', d] => d.is_ascii_digit(),
+            _ => false,
+        };
+        if label_end == 0 || !valid {
+            return Err(ParseError::new(1, "expected a step"));
+        }
+    }
+
+    Ok(input)
+}
+
+// Steps drawn from a small pool of labels, so that lenses actually get
+// replaced and removed.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let labels: Vec<String> = (0..(size / 4).max(1))
+        .map(|_| {
+            (0..rng.range(2..7))
+                .map(|_| char::from(b'a' + rng.below(26) as u8))
+                .collect()
+        })
+        .collect();
+
+    let steps: Vec<String> = (0..size.max(1))
+        .map(|_| {
+            let label = rng.choose(&labels);
+            match rng.chance(1, 3) {
+                true => format!("{}-", label),
+                false => format!("{}={}", label, rng.range(1..10)),
+            }
+        })
+        .collect();
+
+    steps.join(",") + "\n"
 }
 
 #[allow(unused_variables)]
@@ -113,10 +158,11 @@ pub fn part2(input: &str) -> Option<u64> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day15/test.txt");
     #[test]
     fn test_day15_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(input);
 
@@ -125,10 +171,16 @@ pub mod tests {
 
     #[test]
     fn test_day15_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(input);
 
         assert_eq!(resp, Some(145));
     }
+
+    #[test]
+    fn test_day15_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
@@ -1,3 +1,17 @@
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    polygon::{self, Point},
+    rng::Rng,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    // The plan doesn't end where it started. Holds the end point.
+    NotClosed(Point),
+    // Indices of two instructions whose trenches cross, touch or overlap.
+    SelfIntersecting(usize, usize),
+}
+
 #[derive(Debug)]
 pub struct Command {
     x1: i64,
@@ -8,61 +22,47 @@ pub struct Command {
     dist2: i64,
 }
 
-fn area_of_points(points: &[(i64, i64)]) -> u64 {
-    let mut sum = 0;
-    for i in 0..points.len() - 1 {
-        let p1 = points[i];
-        let p2 = points[i + 1];
-
-        let area = (p1.0 - p2.0) * (p1.1 + p2.1);
-        sum += area;
-    }
-
-    if sum < 0 {
-        sum = -sum;
-    }
+// Part 1 distances share the colours' five hex digit limit, so corners and
+// the areas between them stay well inside an i64.
+const MAX_DIST: i64 = 0xfffff;
 
-    sum >>= 1;
-    sum as u64
-}
-
-pub fn parse_input(input: &str) -> Vec<Command> {
+pub fn parse_input(input: &str) -> Result<Vec<Command>, ParseError> {
     let mut commands = Vec::new();
-    for line in input.lines() {
-        let mut chars = line.chars();
-
-        let dir = chars.next().unwrap();
-        chars.next();
-
-        let mut dist1 = chars.next().unwrap() as i64 - '0' as i64;
-        while let Some(c) = chars.next() {
-            if c.is_ascii_digit() {
-                dist1 = 10 * dist1 + c as i64 - '0' as i64;
-            } else {
-                break;
-            }
-        }
-        let (x1, y1) = match dir {
-            'U' => (0, 1),
-            'D' => (0, -1),
-            'L' => (-1, 0),
-            'R' => (1, 0),
-            _ => unreachable!(),
+    for (n, line) in numbered_lines(input) {
+        let mut fields = line.split(' ');
+        let (Some(dir), Some(dist1), Some(colour), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(ParseError::new(
+                n,
+                "expected a direction, distance and colour",
+            ));
         };
 
-        chars.next();
-        chars.next(); // Skip past '#' char
-
-        let s: String = chars.clone().take(5).collect();
-        let dist2: i64 = i64::from_str_radix(&s, 16).unwrap();
-        let dir2 = chars.nth(5).unwrap();
+        let (x1, y1) = match dir {
+            "U" => (0, 1),
+            "D" => (0, -1),
+            "L" => (-1, 0),
+            "R" => (1, 0),
+            _ => return Err(ParseError::new(n, "expected U, D, L or R")),
+        };
+        let dist1: i64 = number(n, dist1)?;
+        if !(0..=MAX_DIST).contains(&dist1) {
+            return Err(ParseError::new(n, "distance out of range"));
+        }
 
-        let (x2, y2) = match dir2 {
-            '3' => (0, 1),
-            '1' => (0, -1),
-            '2' => (-1, 0),
-            '0' => (1, 0),
-            _ => unreachable!(),
+        let hex = colour
+            .strip_prefix("(#")
+            .and_then(|c| c.strip_suffix(')'))
+            .filter(|c| c.len() == 6 && c.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or(ParseError::new(n, "expected a colour"))?;
+        let dist2 = i64::from_str_radix(&hex[..5], 16).unwrap();
+        let (x2, y2) = match &hex[5..] {
+            "3" => (0, 1),
+            "1" => (0, -1),
+            "2" => (-1, 0),
+            "0" => (1, 0),
+            _ => return Err(ParseError::new(n, "expected a direction digit from 0 to 3")),
         };
 
         let command = Command {
@@ -76,60 +76,265 @@ pub fn parse_input(input: &str) -> Vec<Command> {
         commands.push(command);
     }
 
-    commands
+    Ok(commands)
 }
 
-#[allow(unused_variables)]
-pub fn part1(input: &[Command]) -> Option<u64> {
+// Corners of the trench, starting and hopefully ending at the origin. Part 1
+// uses the plain instructions and part 2 the ones hidden in the colours.
+pub fn vertices(input: &[Command], part: u32) -> Vec<Point> {
     let mut curr = (0, 0);
     let mut points = vec![(0, 0)];
 
-    let mut line_sum = 0;
     for command in input {
-        let next_point = (
-            curr.0 + command.x1 * command.dist1,
-            curr.1 + command.y1 * command.dist1,
-        );
+        let (x, y, dist) = match part {
+            1 => (command.x1, command.y1, command.dist1),
+            _ => (command.x2, command.y2, command.dist2),
+        };
+        let next_point = (curr.0 + x * dist, curr.1 + y * dist);
         points.push(next_point);
         curr = next_point;
-        line_sum += command.dist1;
     }
-    line_sum >>= 1;
 
-    let sum = area_of_points(&points) + line_sum as u64 + 1;
+    points
+}
 
-    Some(sum)
+fn bounds(p1: Point, p2: Point) -> (Point, Point) {
+    (
+        (p1.0.min(p2.0), p1.1.min(p2.1)),
+        (p1.0.max(p2.0), p1.1.max(p2.1)),
+    )
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &[Command]) -> Option<u64> {
-    let mut curr = (0, 0);
-    let mut points = vec![(0, 0)];
+// Checks that the plan is a simple closed loop, which is what the area
+// calculation relies on.
+pub fn validate(points: &[Point]) -> Result<(), Error> {
+    let end = points[points.len() - 1];
+    if end != points[0] {
+        return Err(Error::NotClosed(end));
+    }
 
-    let mut line_sum = 0;
-    for command in input {
-        let next_point = (
-            curr.0 + command.x2 * command.dist2,
-            curr.1 + command.y2 * command.dist2,
-        );
-        points.push(next_point);
-        curr = next_point;
-        line_sum += command.dist2;
+    // Instructions that don't move dig nothing new, so they're left out and
+    // the trenches either side of them become neighbours.
+    let segments: Vec<(usize, (Point, Point))> = points
+        .windows(2)
+        .map(|w| (w[0], w[1]))
+        .enumerate()
+        .filter(|(_, (p1, p2))| p1 != p2)
+        .collect();
+    let n = segments.len();
+    for i in 0..n {
+        for j in i + 1..n {
+            let (a, (a1, a2)) = segments[i];
+            let (b, (b1, b2)) = segments[j];
+
+            // Neighbouring trenches share a corner, so they only clash if the
+            // second one doubles back over the first.
+            if j == i + 1 || (i == 0 && j == n - 1) {
+                let dot = (a2.0 - a1.0) * (b2.0 - b1.0) + (a2.1 - a1.1) * (b2.1 - b1.1);
+                if dot < 0 {
+                    return Err(Error::SelfIntersecting(a, b));
+                }
+                continue;
+            }
+
+            let (a_min, a_max) = bounds(a1, a2);
+            let (b_min, b_max) = bounds(b1, b2);
+            if a_min.0.max(b_min.0) <= a_max.0.min(b_max.0)
+                && a_min.1.max(b_min.1) <= a_max.1.min(b_max.1)
+            {
+                return Err(Error::SelfIntersecting(a, b));
+            }
+        }
     }
-    line_sum >>= 1;
 
-    let sum = area_of_points(&points) + line_sum as u64 + 1;
+    Ok(())
+}
+
+pub fn lagoon_size(points: &[Point]) -> Option<u64> {
+    validate(points).ok()?;
 
-    Some(sum)
+    // Every cube dug out is a lattice point either inside or on the trench.
+    Some(polygon::interior_points(points)? + polygon::boundary_points(points))
+}
+
+// Draws the plan scaled to fit an 800px square, with y pointing up and a
+// legend giving the lagoon size and trench length.
+pub fn to_svg(points: &[Point]) -> String {
+    const SIZE: f64 = 800.0;
+    const MARGIN: f64 = 20.0;
+
+    let min_x = points.iter().map(|p| p.0).min().unwrap();
+    let max_x = points.iter().map(|p| p.0).max().unwrap();
+    let min_y = points.iter().map(|p| p.1).min().unwrap();
+    let max_y = points.iter().map(|p| p.1).max().unwrap();
+    let span = (max_x - min_x).max(max_y - min_y).max(1);
+    let scale = SIZE / span as f64;
+    let width = (max_x - min_x) as f64 * scale + 2.0 * MARGIN;
+    let height = (max_y - min_y) as f64 * scale + 2.0 * MARGIN;
+
+    let coords: Vec<String> = points
+        .iter()
+        .map(|&(x, y)| {
+            let sx = (x - min_x) as f64 * scale + MARGIN;
+            let sy = (max_y - y) as f64 * scale + MARGIN;
+            format!("{:.2},{:.2}", sx, sy)
+        })
+        .collect();
+    let shape = match points[0] == points[points.len() - 1] {
+        true => "polygon",
+        false => "polyline",
+    };
+
+    let mut legend = vec![match validate(points) {
+        Ok(()) => format!("Lagoon: {} m³", lagoon_size(points).unwrap_or(0)),
+        Err(Error::NotClosed((x, y))) => format!("Not closed, ends at ({}, {})", x, y),
+        Err(Error::SelfIntersecting(i, j)) => {
+            format!("Instructions {} and {} intersect", i + 1, j + 1)
+        }
+    }];
+    legend.push(format!("Trench: {} m", polygon::boundary_points(points)));
+
+    let mut out = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{:.0}\" height=\"{:.0}\">\n",
+        width,
+        height + 20.0 * legend.len() as f64 + MARGIN
+    );
+    out.push_str(&format!(
+        "  <{} points=\"{}\" fill=\"#c8dcef\" stroke=\"#5a3a1a\" stroke-width=\"{:.2}\" stroke-linejoin=\"miter\"/>\n",
+        shape,
+        coords.join(" "),
+        scale.max(1.0)
+    ));
+    for (i, line) in legend.iter().enumerate() {
+        out.push_str(&format!(
+            "  <text x=\"{:.0}\" y=\"{:.0}\" font-family=\"monospace\" font-size=\"14\">{}</text>\n",
+            MARGIN,
+            height + 20.0 * (i + 1) as f64,
+            line
+        ));
+    }
+    out.push_str("</svg>\n");
+
+    out
+}
+
+// Plans tracing a random skyline around the start, at the puzzle's scale for
+// part 1 and a far bigger one in the colours for part 2. Every distance in
+// the colours has to fit in five hex digits.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let count = (size / 4).max(1);
+    let small = polygon::random_skyline(rng, count, 5 * count as i64, 20);
+    let large = polygon::random_skyline(rng, count, 40000 * count as i64, 500000);
+
+    plan_text(&small, &large)
+}
+
+// A plan whose steps trace `small` and whose colours trace `large`, which
+// must have the same number of corners.
+fn plan_text(small: &[Point], large: &[Point]) -> String {
+    let step = |points: &[Point], i: usize| {
+        let (x1, y1) = points[i];
+        let (x2, y2) = points[(i + 1) % points.len()];
+        let dir = match ((x2 - x1).signum(), (y2 - y1).signum()) {
+            (1, 0) => 0,
+            (0, -1) => 1,
+            (-1, 0) => 2,
+            _ => 3,
+        };
+        (dir, (x2 - x1).abs() + (y2 - y1).abs())
+    };
+
+    let mut out = String::new();
+    for i in 0..small.len() {
+        let (dir1, dist1) = step(small, i);
+        let (dir2, dist2) = step(large, i);
+        out.push_str(&format!(
+            "{} {} (#{:05x}{})\n",
+            ['R', 'D', 'L', 'U'][dir1],
+            dist1,
+            dist2,
+            dir2
+        ));
+    }
+
+    out
+}
+
+// Digs the trench out one cube at a time and floods the ground around it, for
+// cross-checking the shoelace formula. Only usable on small plans.
+#[cfg(test)]
+fn reference_size(points: &[Point]) -> Option<u64> {
+    validate(points).ok()?;
+
+    let min_x = points.iter().map(|p| p.0).min()? - 1;
+    let min_y = points.iter().map(|p| p.1).min()? - 1;
+    let width = (points.iter().map(|p| p.0).max()? - min_x + 2) as usize;
+    let height = (points.iter().map(|p| p.1).max()? - min_y + 2) as usize;
+
+    let mut dug = vec![vec![false; width]; height];
+    for w in points.windows(2) {
+        let ((x1, y1), (x2, y2)) = (w[0], w[1]);
+        for x in x1.min(x2)..=x1.max(x2) {
+            for y in y1.min(y2)..=y1.max(y2) {
+                dug[(y - min_y) as usize][(x - min_x) as usize] = true;
+            }
+        }
+    }
+
+    let mut outside = vec![vec![false; width]; height];
+    let mut stack = vec![(0, 0)];
+    while let Some((x, y)) = stack.pop() {
+        if outside[y][x] || dug[y][x] {
+            continue;
+        }
+        outside[y][x] = true;
+        if x > 0 {
+            stack.push((x - 1, y));
+        }
+        if y > 0 {
+            stack.push((x, y - 1));
+        }
+        if x + 1 < width {
+            stack.push((x + 1, y));
+        }
+        if y + 1 < height {
+            stack.push((x, y + 1));
+        }
+    }
+
+    let flooded: usize = outside.iter().flatten().filter(|&&o| o).count();
+    Some((width * height - flooded) as u64)
+}
+
+#[cfg(test)]
+pub fn reference_part1(data: &str) -> Option<u64> {
+    reference_size(&vertices(&parse_input(data).ok()?, 1))
+}
+
+#[cfg(test)]
+pub fn reference_part2(data: &str) -> Option<u64> {
+    reference_size(&vertices(&parse_input(data).ok()?, 2))
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &[Command]) -> Option<u64> {
+    lagoon_size(&vertices(input, 1))
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &[Command]) -> Option<u64> {
+    lagoon_size(&vertices(input, 2))
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
+    use crate::utils::property::assert_agree;
     const TEST_INPUT: &str = include_str!("../../input/day18/test.txt");
     #[test]
     fn test_day18_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -138,10 +343,110 @@ pub mod tests {
 
     #[test]
     fn test_day18_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(952408144115));
     }
+
+    #[test]
+    fn test_day18_validate() {
+        let input = parse_input(TEST_INPUT).unwrap();
+        assert_eq!(validate(&vertices(&input, 1)), Ok(()));
+        assert_eq!(validate(&vertices(&input, 2)), Ok(()));
+
+        let input =
+            parse_input(&TEST_INPUT.lines().skip(1).collect::<Vec<_>>().join("\n")).unwrap();
+        assert_eq!(
+            validate(&vertices(&input, 1)),
+            Err(Error::NotClosed((-6, 0)))
+        );
+        assert_eq!(part1(&input), None);
+
+        // A figure of eight crosses itself at the origin.
+        let eight = "R 2 (#000000)\nU 1 (#000000)\nL 1 (#000000)\nD 2 (#000000)\n\
+                     L 1 (#000000)\nU 1 (#000000)";
+        let input = parse_input(eight).unwrap();
+        assert_eq!(
+            validate(&vertices(&input, 1)),
+            Err(Error::SelfIntersecting(0, 3))
+        );
+
+        let back = "R 2 (#000000)\nL 1 (#000000)\nU 1 (#000000)\nL 1 (#000000)\nD 1 (#000000)";
+        let input = parse_input(back).unwrap();
+        assert_eq!(
+            validate(&vertices(&input, 1)),
+            Err(Error::SelfIntersecting(0, 1))
+        );
+
+        // Zero distances don't count as trenches, even at the ends.
+        let zeros = "R 0 (#000000)\nR 2 (#000000)\nU 0 (#000000)\nU 2 (#000000)\n\
+                     L 2 (#000000)\nD 2 (#000000)\nD 0 (#000000)";
+        let input = parse_input(zeros).unwrap();
+        assert_eq!(validate(&vertices(&input, 1)), Ok(()));
+        assert_eq!(part1(&input), Some(9));
+
+        let back = "R 2 (#000000)\nU 0 (#000000)\nL 1 (#000000)\nU 1 (#000000)\n\
+                    L 1 (#000000)\nD 1 (#000000)";
+        let input = parse_input(back).unwrap();
+        assert_eq!(
+            validate(&vertices(&input, 1)),
+            Err(Error::SelfIntersecting(0, 2))
+        );
+    }
+
+    #[test]
+    fn test_day18_to_svg() {
+        let input = parse_input(TEST_INPUT).unwrap();
+
+        let svg = to_svg(&vertices(&input, 1));
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains("<polygon points=\"20.00,20.00 "));
+        assert!(svg.contains(">Lagoon: 62 m³</text>"));
+        assert!(svg.contains(">Trench: 38 m</text>"));
+
+        let svg = to_svg(&vertices(&input, 2));
+        assert!(svg.contains(">Lagoon: 952408144115 m³</text>"));
+    }
+
+    // As `generate`, with the bar width and height of the part 2 skyline given.
+    fn generate_with(rng: &mut Rng, size: usize, bar_width: i64, height: i64) -> String {
+        let count = (size / 4).max(1);
+        let small = polygon::random_skyline(rng, count, 5 * count as i64, 20);
+        let large = polygon::random_skyline(rng, count, bar_width * count as i64, height);
+
+        plan_text(&small, &large)
+    }
+
+    #[test]
+    fn test_day18_reference() {
+        assert_eq!(reference_part1(TEST_INPUT), Some(62));
+
+        // Keep the colour plans small enough to dig out by hand.
+        let generate = |rng: &mut Rng| generate_with(rng, 24, 6, 15);
+        assert_agree(
+            100,
+            generate,
+            |s| part1(&parse_input(s).unwrap()),
+            reference_part1,
+        );
+        assert_agree(
+            100,
+            generate,
+            |s| part2(&parse_input(s).unwrap()),
+            reference_part2,
+        );
+
+        // Both give up on a plan that crosses itself.
+        let eight = "R 2 (#000000)\nU 1 (#000000)\nL 1 (#000000)\nD 2 (#000000)\n\
+                     L 1 (#000000)\nU 1 (#000000)";
+        assert_eq!(reference_part1(eight), None);
+    }
+
+    #[test]
+    fn test_day18_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
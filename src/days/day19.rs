@@ -1,12 +1,21 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
 
 use nom::{
     bytes::complete::tag,
-    character::complete::{alpha1, anychar, digit1},
-    sequence::preceded,
+    character::complete::{alpha1, digit1, one_of},
+    combinator::{all_consuming, map_res},
+    sequence::{preceded, tuple},
     IResult,
 };
 
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    rng::Rng,
+};
+
 #[derive(Debug)]
 pub enum Op {
     LessThan,
@@ -23,6 +32,10 @@ pub struct Part {
 }
 
 impl Part {
+    pub fn new(x: i32, m: i32, a: i32, s: i32) -> Part {
+        Part { x, m, a, s }
+    }
+
     pub fn get(&self, c: char) -> i32 {
         match c {
             'x' => self.x,
@@ -34,7 +47,8 @@ impl Part {
     }
 }
 
-#[derive(Debug)]
+// Inclusive bounds for each rating.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PartRange {
     x: (u64, u64),
     m: (u64, u64),
@@ -43,6 +57,56 @@ pub struct PartRange {
 }
 
 impl PartRange {
+    // None if any rating's bounds are the wrong way round, which would make
+    // the range empty.
+    pub fn new(x: (u64, u64), m: (u64, u64), a: (u64, u64), s: (u64, u64)) -> Option<PartRange> {
+        if [x, m, a, s].iter().any(|(l, h)| l > h) {
+            return None;
+        }
+
+        Some(PartRange { x, m, a, s })
+    }
+
+    // Every part the puzzle allows, 1 to 4000 for each rating.
+    pub fn full() -> PartRange {
+        PartRange {
+            x: (1, 4000),
+            m: (1, 4000),
+            a: (1, 4000),
+            s: (1, 4000),
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        [self.x, self.m, self.a, self.s]
+            .iter()
+            .map(|(l, h)| (h + 1).saturating_sub(*l))
+            .product()
+    }
+
+    pub fn contains(&self, part: &Part) -> bool {
+        "xmas".chars().all(|attr| {
+            let (l, h) = self.get(attr);
+            let value = part.get(attr) as u64;
+            l <= value && value <= h
+        })
+    }
+
+    pub fn intersect(&self, other: &PartRange) -> Option<PartRange> {
+        let mut range = *self;
+        for attr in "xmas".chars() {
+            let (l1, h1) = self.get(attr);
+            let (l2, h2) = other.get(attr);
+            let (l, h) = (l1.max(l2), h1.min(h2));
+            if l > h {
+                return None;
+            }
+            range.set(attr, (l, h));
+        }
+
+        Some(range)
+    }
+
     pub fn get(&self, attr: char) -> (u64, u64) {
         match attr {
             'x' => self.x,
@@ -65,7 +129,7 @@ impl PartRange {
 
     pub fn from_break(mut self, attr: char, value: u64) -> (Option<PartRange>, Option<PartRange>) {
         let (l, h) = self.get(attr);
-        if value < l {
+        if value <= l {
             return (None, Some(self));
         }
         if h < value {
@@ -93,6 +157,38 @@ pub struct Rule<'a> {
     to: &'a str,
 }
 
+impl Rule<'_> {
+    pub fn matches(&self, part: &Part) -> bool {
+        match self.op {
+            Op::LessThan => part.get(self.attr) < self.value,
+            Op::GreaterThan => part.get(self.attr) > self.value,
+            Op::None => true,
+        }
+    }
+
+    // Splits a range into the parts that do and don't match this rule.
+    pub fn split(&self, range: PartRange) -> (Option<PartRange>, Option<PartRange>) {
+        match self.op {
+            Op::LessThan => range.from_break(self.attr, self.value as u64),
+            Op::GreaterThan => {
+                let (below, above) = range.from_break(self.attr, self.value as u64 + 1);
+                (above, below)
+            }
+            Op::None => (Some(range), None),
+        }
+    }
+}
+
+impl fmt::Display for Rule<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.op {
+            Op::LessThan => write!(f, "{}<{}", self.attr, self.value),
+            Op::GreaterThan => write!(f, "{}>{}", self.attr, self.value),
+            Op::None => Ok(()),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Workflow<'a> {
     rules: Vec<Rule<'a>>,
@@ -105,33 +201,31 @@ pub struct Input<'a> {
     parts: Vec<Part>,
 }
 
-fn parse_rule(input: &str) -> IResult<&str, Rule> {
-    let (input, s) = alpha1(input)?;
+fn parse_condition(input: &str) -> IResult<&str, (char, char, i32)> {
+    tuple((one_of("xmas"), one_of("<>"), map_res(digit1, str::parse)))(input)
+}
 
-    if input.is_empty() {
+fn parse_rule(input: &str) -> IResult<&str, Rule> {
+    let Ok((input, (attr, op, value))) = parse_condition(input) else {
+        let (input, to) = alpha1(input)?;
         let rule = Rule {
             attr: 'N',
             op: Op::None,
             value: 0,
-            to: s,
+            to,
         };
         return Ok((input, rule));
-    }
+    };
 
-    let (input, op) = anychar(input)?;
     let op = match op {
         '>' => Op::GreaterThan,
-        '<' => Op::LessThan,
-        _ => unreachable!(),
+        _ => Op::LessThan,
     };
 
-    let (input, value) = digit1(input)?;
-    let value = value.parse().unwrap();
-
     let (input, to) = preceded(tag(":"), alpha1)(input)?;
 
     let rule = Rule {
-        attr: s.chars().next().unwrap(),
+        attr,
         op,
         value,
         to,
@@ -140,37 +234,40 @@ fn parse_rule(input: &str) -> IResult<&str, Rule> {
     Ok((input, rule))
 }
 
-pub fn parse_input(input: &str) -> Input {
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
     let mut workflow_map = HashMap::new();
 
-    let mut lines = input.lines();
-    for line in lines.by_ref() {
+    let mut lines = numbered_lines(input);
+    for (n, line) in lines.by_ref() {
         if line.is_empty() {
             break;
         }
-        let line = line.strip_suffix('}').unwrap();
-        let mut split = line.split('{');
-        let workflow_name = split.next().unwrap();
-        let line = split.next().unwrap();
+        let (workflow_name, line) = line
+            .strip_suffix('}')
+            .and_then(|line| line.split_once('{'))
+            .ok_or(ParseError::new(n, "expected a workflow"))?;
 
         let mut rules = Vec::new();
         let rule_splits = line.split(',');
         for rule_split in rule_splits {
-            if let Ok((_, rule)) = parse_rule(rule_split) {
-                rules.push(rule);
-            } else {
-                unreachable!()
+            match all_consuming(parse_rule)(rule_split) {
+                Ok((_, rule)) => rules.push(rule),
+                Err(_) => return Err(ParseError::new(n, "expected a rule")),
             }
         }
 
         let workflow = Workflow { rules };
-        workflow_map.insert(workflow_name, workflow);
+        if workflow_map.insert(workflow_name, workflow).is_some() {
+            return Err(ParseError::new(n, "workflow defined twice"));
+        }
     }
 
     let mut parts = Vec::new();
-    for line in lines {
-        let line = line.strip_prefix('{').unwrap();
-        let line = line.strip_suffix('}').unwrap();
+    for (n, line) in lines {
+        let line = line
+            .strip_prefix('{')
+            .and_then(|line| line.strip_suffix('}'))
+            .ok_or(ParseError::new(n, "expected a part"))?;
 
         let mut part = Part {
             x: 0,
@@ -179,138 +276,507 @@ pub fn parse_input(input: &str) -> Input {
             s: 0,
         };
         for split in line.split(',') {
-            let mut chars = split.chars();
-            let attr = chars.next().unwrap();
-            chars.next();
-
-            let mut value = 0;
-            for c in chars {
-                if c.is_ascii_digit() {
-                    value = 10 * value + c as i32 - '0' as i32;
-                }
+            let (attr, value) = split
+                .split_once('=')
+                .ok_or(ParseError::new(n, "expected a rating"))?;
+            let value = number(n, value)?;
+            if !(1..=4000).contains(&value) {
+                return Err(ParseError::new(n, "rating out of range"));
             }
 
             match attr {
-                'x' => part.x = value,
-                'm' => part.m = value,
-                'a' => part.a = value,
-                's' => part.s = value,
-                _ => unreachable!(),
+                "x" => part.x = value,
+                "m" => part.m = value,
+                "a" => part.a = value,
+                "s" => part.s = value,
+                _ => return Err(ParseError::new(n, "expected x, m, a or s")),
             }
         }
 
         parts.push(part);
     }
 
-    Input {
+    Ok(Input {
         start: "in".to_string(),
         workflow_map,
         parts,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Issue<'a> {
+    MissingStart(&'a str),
+    UnknownTarget { workflow: &'a str, target: &'a str },
+    Unreachable(&'a str),
+    Cycle(Vec<&'a str>),
+    // Rules are numbered from 1 within their workflow.
+    DeadRule { workflow: &'a str, rule: usize },
+    ConstantRoute { workflow: &'a str, to: &'a str },
+}
+
+impl Issue<'_> {
+    // Issues that make part 1 or part 2 panic or never finish.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Issue::MissingStart(_) | Issue::UnknownTarget { .. } | Issue::Cycle(_)
+        )
     }
 }
 
-#[allow(unused_variables)]
-pub fn part1(input: &Input) -> Option<u64> {
-    let mut sum = 0;
-    for part in &input.parts {
-        let mut curr = input.start.as_str();
-        loop {
-            if curr == "A" {
-                sum += part.x;
-                sum += part.m;
-                sum += part.a;
-                sum += part.s;
-                break;
+impl fmt::Display for Issue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::MissingStart(name) => write!(f, "start workflow {} doesn't exist", name),
+            Issue::UnknownTarget { workflow, target } => {
+                write!(
+                    f,
+                    "{}: sends parts to unknown workflow {}",
+                    workflow, target
+                )
+            }
+            Issue::Unreachable(workflow) => write!(f, "{}: can never be reached", workflow),
+            Issue::Cycle(path) => write!(f, "cycle: {} -> {}", path.join(" -> "), path[0]),
+            Issue::DeadRule { workflow, rule } => {
+                write!(f, "{}: rule {} can never match", workflow, rule)
             }
-            if curr == "R" {
-                break;
+            Issue::ConstantRoute { workflow, to } => {
+                write!(f, "{}: always sends parts to {}", workflow, to)
             }
+        }
+    }
+}
 
-            let workflow = input.workflow_map.get(curr).unwrap();
+fn is_terminal(name: &str) -> bool {
+    name == "A" || name == "R"
+}
 
+fn find_cycles<'a>(
+    curr: &'a str,
+    workflow_map: &HashMap<&'a str, Workflow<'a>>,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+    cycles: &mut Vec<Vec<&'a str>>,
+) {
+    if let Some(pos) = path.iter().position(|&w| w == curr) {
+        cycles.push(path[pos..].to_vec());
+        return;
+    }
+    if done.contains(curr) {
+        return;
+    }
+    let Some(workflow) = workflow_map.get(curr) else {
+        return;
+    };
+
+    path.push(curr);
+    let mut targets: Vec<&str> = workflow.rules.iter().map(|rule| rule.to).collect();
+    targets.dedup();
+    for target in targets {
+        find_cycles(target, workflow_map, path, done, cycles);
+    }
+    path.pop();
+    done.insert(curr);
+}
+
+// Checks the workflows for anything that would break part 1 or part 2, along
+// with rules and workflows that don't do anything useful.
+pub fn validate<'a>(input: &'a Input) -> Vec<Issue<'a>> {
+    let mut issues = Vec::new();
+    let workflow_map = &input.workflow_map;
+    let mut names: Vec<&str> = workflow_map.keys().copied().collect();
+    names.sort();
+
+    let start = input.start.as_str();
+    if !workflow_map.contains_key(start) {
+        issues.push(Issue::MissingStart(start));
+    }
+
+    for &name in &names {
+        for rule in &workflow_map[name].rules {
+            if !is_terminal(rule.to) && !workflow_map.contains_key(rule.to) {
+                issues.push(Issue::UnknownTarget {
+                    workflow: name,
+                    target: rule.to,
+                });
+            }
+        }
+    }
+
+    let mut reached = HashSet::from([start]);
+    let mut to_visit = vec![start];
+    while let Some(curr) = to_visit.pop() {
+        if let Some(workflow) = workflow_map.get(curr) {
             for rule in &workflow.rules {
-                let matches = match rule.op {
-                    Op::LessThan => part.get(rule.attr) < rule.value,
-                    Op::GreaterThan => part.get(rule.attr) > rule.value,
-                    Op::None => true,
-                };
-
-                if matches {
-                    curr = rule.to;
-                    break;
+                if reached.insert(rule.to) {
+                    to_visit.push(rule.to);
                 }
             }
         }
     }
+    for &name in &names {
+        if !reached.contains(name) {
+            issues.push(Issue::Unreachable(name));
+        }
+    }
 
-    Some(sum as u64)
+    let mut cycles = Vec::new();
+    let mut done = HashSet::new();
+    for &name in &names {
+        find_cycles(name, workflow_map, &mut Vec::new(), &mut done, &mut cycles);
+    }
+    issues.extend(cycles.into_iter().map(Issue::Cycle));
+
+    for &name in &names {
+        let mut remaining = Some(PartRange::full());
+        let mut destinations = HashSet::new();
+        for (i, rule) in workflow_map[name].rules.iter().enumerate() {
+            let matched = match remaining {
+                Some(range) => {
+                    let (matched, unmatched) = rule.split(range);
+                    remaining = unmatched;
+                    matched
+                }
+                None => None,
+            };
+
+            match matched {
+                Some(_) => {
+                    destinations.insert(rule.to);
+                }
+                None => issues.push(Issue::DeadRule {
+                    workflow: name,
+                    rule: i + 1,
+                }),
+            }
+        }
+        // Parts that match no rule are rejected.
+        if remaining.is_some() {
+            destinations.insert("R");
+        }
+
+        if destinations.len() == 1 {
+            issues.push(Issue::ConstantRoute {
+                workflow: name,
+                to: destinations.into_iter().next().unwrap(),
+            });
+        }
+    }
+
+    issues
 }
 
-fn do_ranges(curr: &str, workflow_map: &HashMap<&str, Workflow>, part_range: PartRange) -> u64 {
-    if curr == "A" {
-        let mut prod = 1;
-        prod *= part_range.x.1 - part_range.x.0 + 1;
-        prod *= part_range.m.1 - part_range.m.0 + 1;
-        prod *= part_range.a.1 - part_range.a.0 + 1;
-        prod *= part_range.s.1 - part_range.s.0 + 1;
-        return prod;
+// A tree of workflows hanging off `in`, so every workflow is reachable and
+// none loop, followed by `size` random parts.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let count = size.max(1);
+    let name = |k: usize| {
+        let mut n = k + 26;
+        let mut letters = Vec::new();
+        while n > 0 {
+            letters.push(b'a' + (n % 26) as u8);
+            n /= 26;
+        }
+        letters.reverse();
+        String::from_utf8(letters).unwrap()
+    };
+    let names: Vec<String> = std::iter::once("in".to_string())
+        .chain((0..).map(name).filter(|n| n != "in"))
+        .take(count)
+        .collect();
+
+    let mut children: Vec<Vec<&str>> = vec![Vec::new(); count];
+    for (j, child) in names.iter().enumerate().skip(1) {
+        children[rng.below(j)].push(child);
     }
-    if curr == "R" {
-        return 0;
+
+    let mut lines = Vec::new();
+    for (workflow, mut targets) in names.iter().zip(children) {
+        while targets.len() < 2 || (targets.len() < 5 && rng.chance(1, 3)) {
+            targets.push(*rng.choose(&["A", "R"]));
+        }
+        rng.shuffle(&mut targets);
+
+        let fallback = targets.pop().unwrap();
+        let rules: Vec<String> = targets
+            .iter()
+            .map(|target| {
+                format!(
+                    "{}{}{}:{}",
+                    rng.choose(&['x', 'm', 'a', 's']),
+                    rng.choose(&['<', '>']),
+                    rng.range(1..4001),
+                    target
+                )
+            })
+            .collect();
+        lines.push(format!("{}{{{},{}}}", workflow, rules.join(","), fallback));
     }
+    rng.shuffle(&mut lines);
+
+    let mut out = lines.join("\n") + "\n\n";
+    for _ in 0..count {
+        let [x, m, a, s] = [(); 4].map(|_| rng.range(1..4001));
+        out.push_str(&format!("{{x={},m={},a={},s={}}}\n", x, m, a, s));
+    }
+
+    out
+}
+
+// Follows a single part through the workflows. Assumes they validate.
+fn is_accepted(input: &Input, part: &Part) -> bool {
+    let mut curr = input.start.as_str();
+    while curr != "A" && curr != "R" {
+        let workflow = &input.workflow_map[curr];
+        curr = workflow
+            .rules
+            .iter()
+            .find(|rule| rule.matches(part))
+            .map_or("R", |rule| rule.to);
+    }
+
+    curr == "A"
+}
+
+// Cuts each rating at every threshold a rule uses, so every part in a cell
+// takes the same path, then walks one part per cell. A slow check on the
+// range splitting in part 2.
+#[cfg(test)]
+pub fn reference_part2(data: &str) -> Option<u64> {
+    let input = parse_input(data).ok()?;
+    if validate(&input).iter().any(Issue::is_fatal) {
+        return None;
+    }
+
+    let cuts = |attr: char| {
+        let mut cuts = vec![1, 4001];
+        for rule in input.workflow_map.values().flat_map(|w| &w.rules) {
+            let cut = match rule.op {
+                _ if rule.attr != attr => continue,
+                Op::LessThan => rule.value,
+                Op::GreaterThan => rule.value + 1,
+                Op::None => continue,
+            };
+            cuts.push(cut.clamp(1, 4001));
+        }
+        cuts.sort();
+        cuts.dedup();
+        cuts
+    };
+    let [xs, ms, as_, ss] = ['x', 'm', 'a', 's'].map(cuts);
 
     let mut sum = 0;
-    let mut curr_range = part_range;
-    let workflow = workflow_map.get(curr).unwrap();
-    for rule in &workflow.rules {
-        // Split range based on the rule.
-        let (matched_range, unmatched_range) = match rule.op {
-            Op::LessThan => {
-                let (range1, range2) = curr_range.from_break(rule.attr, rule.value as u64);
-                (range1, range2)
-            }
-            Op::GreaterThan => {
-                let (range1, range2) = curr_range.from_break(rule.attr, rule.value as u64 + 1);
-                (range2, range1)
+    for x in xs.windows(2) {
+        for m in ms.windows(2) {
+            for a in as_.windows(2) {
+                for s in ss.windows(2) {
+                    if is_accepted(&input, &Part::new(x[0], m[0], a[0], s[0])) {
+                        let size = [x, m, a, s].map(|c| (c[1] - c[0]) as u64);
+                        sum += size.iter().product::<u64>();
+                    }
+                }
             }
-            Op::None => (Some(curr_range), None),
-        };
+        }
+    }
+
+    Some(sum)
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &Input) -> Option<u64> {
+    if validate(input).iter().any(Issue::is_fatal) {
+        return None;
+    }
+
+    let sum: i32 = input
+        .parts
+        .iter()
+        .filter(|part| is_accepted(input, part))
+        .map(|part| part.x + part.m + part.a + part.s)
+        .sum();
+
+    Some(sum as u64)
+}
+
+// Splits `part_range` at each rule on its way through the workflows from
+// `curr`. `leaf` gets every piece that ends up at A or R and gives back a value
+// for it, and `edge` gets each (workflow, rule) edge taken along with the total
+// of the leaves under it. Returns the total of all the leaves.
+fn walk_ranges<'a>(
+    curr: &'a str,
+    workflow_map: &HashMap<&'a str, Workflow<'a>>,
+    part_range: PartRange,
+    leaf: &mut impl FnMut(&'a str, PartRange) -> u64,
+    edge: &mut impl FnMut(&'a str, usize, u64),
+) -> u64 {
+    if is_terminal(curr) {
+        return leaf(curr, part_range);
+    }
+
+    let mut sum = 0;
+    let mut curr_range = part_range;
+    for (i, rule) in workflow_map[curr].rules.iter().enumerate() {
+        let (matched_range, unmatched_range) = rule.split(curr_range);
 
         if let Some(matched_range) = matched_range {
-            sum += do_ranges(rule.to, workflow_map, matched_range);
+            let total = walk_ranges(rule.to, workflow_map, matched_range, leaf, edge);
+            edge(curr, i, total);
+            sum += total;
         }
-        if let Some(unmatched_range) = unmatched_range {
-            curr_range = unmatched_range;
-        } else {
-            break;
+        match unmatched_range {
+            Some(unmatched_range) => curr_range = unmatched_range,
+            None => break,
         }
     }
 
     sum
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &Input) -> Option<u64> {
-    let curr = "in";
-    let range = PartRange {
-        x: (1, 4000),
-        m: (1, 4000),
-        a: (1, 4000),
-        s: (1, 4000),
+fn do_ranges(
+    curr: &str,
+    workflow_map: &HashMap<&str, Workflow>,
+    part_range: PartRange,
+    accepted: &mut Vec<PartRange>,
+) {
+    let mut leaf = |name, range| {
+        if name == "A" {
+            accepted.push(range);
+        }
+        0
     };
+    walk_ranges(curr, workflow_map, part_range, &mut leaf, &mut |_, _, _| {});
+}
 
-    let sum = do_ranges(curr, &input.workflow_map, range);
+// Splits `start` into disjoint boxes whose parts all end up accepted. Fails
+// with the fatal issues if the workflows can't be followed safely.
+pub fn accepted_regions<'a>(
+    input: &'a Input,
+    start: PartRange,
+) -> Result<Vec<PartRange>, Vec<Issue<'a>>> {
+    let fatal: Vec<Issue> = validate(input)
+        .into_iter()
+        .filter(Issue::is_fatal)
+        .collect();
+    if !fatal.is_empty() {
+        return Err(fatal);
+    }
 
-    Some(sum)
+    let mut accepted = Vec::new();
+    do_ranges(&input.start, &input.workflow_map, start, &mut accepted);
+    Ok(accepted)
+}
+
+// Answers queries from the accepted regions instead of walking the workflows.
+#[derive(Debug)]
+pub struct Classifier {
+    regions: Vec<PartRange>,
+}
+
+impl Classifier {
+    pub fn new<'a>(input: &'a Input) -> Result<Classifier, Vec<Issue<'a>>> {
+        Ok(Classifier {
+            regions: accepted_regions(input, PartRange::full())?,
+        })
+    }
+
+    pub fn regions(&self) -> &[PartRange] {
+        &self.regions
+    }
+
+    pub fn accepts(&self, part: &Part) -> bool {
+        self.regions.iter().any(|region| region.contains(part))
+    }
+
+    // Number of accepted parts inside the query box. Regions don't overlap,
+    // so their intersections can just be added up.
+    pub fn count_in(&self, query: &PartRange) -> u64 {
+        self.regions
+            .iter()
+            .filter_map(|region| region.intersect(query))
+            .map(|region| region.count())
+            .sum()
+    }
+}
+
+// Counts the accepted parts in `part_range`, adding how many of them take each
+// (workflow, rule) edge along the way.
+fn edge_flows<'a>(
+    curr: &'a str,
+    workflow_map: &HashMap<&'a str, Workflow<'a>>,
+    part_range: PartRange,
+    flows: &mut HashMap<(&'a str, usize), u64>,
+) -> u64 {
+    let mut leaf = |name, range: PartRange| match name {
+        "A" => range.count(),
+        _ => 0,
+    };
+    let mut edge = |workflow, rule, accepted| {
+        *flows.entry((workflow, rule)).or_insert(0) += accepted;
+    };
+    walk_ranges(curr, workflow_map, part_range, &mut leaf, &mut edge)
+}
+
+// Writes the workflows as a Graphviz digraph with each edge labelled by its
+// rule. With `annotate`, edges also show how many accepted combinations go
+// through them.
+pub fn to_dot(input: &Input, annotate: bool) -> String {
+    let mut flows = HashMap::new();
+    let annotate = annotate && !validate(input).iter().any(Issue::is_fatal);
+    if annotate {
+        edge_flows(
+            &input.start,
+            &input.workflow_map,
+            PartRange::full(),
+            &mut flows,
+        );
+    }
+
+    let mut names: Vec<&str> = input.workflow_map.keys().copied().collect();
+    names.sort();
+
+    let mut out = String::from("digraph workflows {\n");
+    out.push_str("    node [shape=box];\n");
+    out.push_str("    A [shape=doublecircle, style=filled, fillcolor=palegreen];\n");
+    out.push_str("    R [shape=doublecircle, style=filled, fillcolor=lightpink];\n");
+    if input.workflow_map.contains_key(input.start.as_str()) {
+        out.push_str(&format!("    {} [peripheries=2];\n", input.start));
+    }
+    for &name in &names {
+        for (i, rule) in input.workflow_map[name].rules.iter().enumerate() {
+            let mut label = rule.to_string();
+            if annotate {
+                let flow = flows.get(&(name, i)).copied().unwrap_or(0);
+                if !label.is_empty() {
+                    label.push_str("\\n");
+                }
+                label.push_str(&flow.to_string());
+            }
+            out.push_str(&format!(
+                "    {} -> {} [label=\"{}\"];\n",
+                name, rule.to, label
+            ));
+        }
+    }
+    out.push_str("}\n");
+
+    out
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &Input) -> Option<u64> {
+    let regions = accepted_regions(input, PartRange::full()).ok()?;
+    Some(regions.iter().map(|region| region.count()).sum())
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
+    use crate::utils::property::assert_agree;
     const TEST_INPUT: &str = include_str!("../../input/day19/test.txt");
     #[test]
     fn test_day19_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -319,10 +785,166 @@ pub mod tests {
 
     #[test]
     fn test_day19_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(167409079868000));
     }
+
+    #[test]
+    fn test_day19_validate() {
+        let input = parse_input(TEST_INPUT).unwrap();
+        let issues = validate(&input);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::ConstantRoute {
+                    workflow: "gd",
+                    to: "R"
+                },
+                Issue::ConstantRoute {
+                    workflow: "lnx",
+                    to: "A"
+                },
+            ]
+        );
+
+        let input = parse_input("in{x<10:a,x<5:R,b}\na{m>10:in,A}\nb{s<3:zz,R}\nc{A}").unwrap();
+        let issues = validate(&input);
+        assert_eq!(
+            issues,
+            vec![
+                Issue::UnknownTarget {
+                    workflow: "b",
+                    target: "zz"
+                },
+                Issue::Unreachable("c"),
+                Issue::Cycle(vec!["a", "in"]),
+                Issue::ConstantRoute {
+                    workflow: "c",
+                    to: "A"
+                },
+                Issue::DeadRule {
+                    workflow: "in",
+                    rule: 2
+                },
+            ]
+        );
+        assert_eq!(issues[2].to_string(), "cycle: a -> in -> a");
+        assert_eq!(part1(&input), None);
+        assert_eq!(part2(&input), None);
+        assert_eq!(
+            accepted_regions(&input, PartRange::full()),
+            Err(vec![issues[0].clone(), issues[2].clone()])
+        );
+        assert!(Classifier::new(&input).is_err());
+
+        // Parts falling off the end of a workflow count as a route to R.
+        let input = parse_input("in{a<5:A}\n\n{x=1,m=1,a=4000,s=1}").unwrap();
+        assert_eq!(validate(&input), vec![]);
+        assert_eq!(part1(&input), Some(0));
+        let input = parse_input("in{a<5:R}").unwrap();
+        assert_eq!(
+            validate(&input),
+            vec![Issue::ConstantRoute {
+                workflow: "in",
+                to: "R"
+            }]
+        );
+    }
+
+    #[test]
+    fn test_day19_classifier() {
+        let input = parse_input(TEST_INPUT).unwrap();
+        let classifier = Classifier::new(&input).unwrap();
+
+        let accepted: Vec<bool> = input.parts.iter().map(|p| classifier.accepts(p)).collect();
+        assert_eq!(accepted, vec![true, false, true, false, true]);
+        assert_eq!(classifier.count_in(&PartRange::full()), 167409079868000);
+
+        for part in &input.parts {
+            let (x, m, a, s) = (part.x as u64, part.m as u64, part.a as u64, part.s as u64);
+            let single = PartRange::new((x, x), (m, m), (a, a), (s, s)).unwrap();
+            assert_eq!(
+                classifier.count_in(&single),
+                classifier.accepts(part) as u64
+            );
+        }
+
+        let query = PartRange::new((1, 1415), (2000, 3000), (1, 4000), (1350, 2770)).unwrap();
+        let regions = accepted_regions(&input, query).unwrap();
+        let total: u64 = regions.iter().map(|region| region.count()).sum();
+        assert_eq!(total, classifier.count_in(&query));
+        assert!(regions
+            .iter()
+            .all(|region| query.intersect(region) == Some(*region)));
+
+        assert_eq!(
+            PartRange::new((1, 4000), (10, 9), (1, 4000), (1, 4000)),
+            None
+        );
+        let single = PartRange::new((5, 5), (1, 4000), (1, 4000), (4000, 4000)).unwrap();
+        assert_eq!(single.count(), 4000 * 4000);
+
+        let inside = Part::new(1000, 2500, 1, 2000);
+        assert!(query.contains(&inside));
+        assert_eq!(
+            classifier.accepts(&inside),
+            regions.iter().any(|region| region.contains(&inside))
+        );
+    }
+
+    #[test]
+    fn test_day19_to_dot() {
+        let input = parse_input(TEST_INPUT).unwrap();
+
+        let dot = to_dot(&input, false);
+        assert!(dot.contains("    in -> px [label=\"s<1351\"];\n"));
+        assert!(dot.contains("    in -> qqz [label=\"\"];\n"));
+        assert!(dot.contains("    in [peripheries=2];\n"));
+
+        // Everything accepted leaves `in` through one of its two edges.
+        let dot = to_dot(&input, true);
+        let flow = |edge: &str| -> u64 {
+            let line = dot.lines().find(|line| line.contains(edge)).unwrap();
+            let label = line.split('"').nth(1).unwrap();
+            label.rsplit("\\n").next().unwrap().parse().unwrap()
+        };
+        assert_eq!(flow("in -> px") + flow("in -> qqz"), part2(&input).unwrap());
+        assert_eq!(flow("gd -> R"), 0);
+    }
+
+    #[test]
+    fn test_day19_reference() {
+        assert_eq!(reference_part2(TEST_INPUT), Some(167409079868000));
+
+        let generate = |rng: &mut Rng| generate(rng, 6);
+        assert_agree(
+            50,
+            generate,
+            |s| part2(&parse_input(s).unwrap()),
+            reference_part2,
+        );
+
+        // Every part on either side of each rule's threshold, checked
+        // against the regions.
+        let input = parse_input(TEST_INPUT).unwrap();
+        let classifier = Classifier::new(&input).unwrap();
+        for rule in input.workflow_map.values().flat_map(|w| &w.rules) {
+            for value in [rule.value - 1, rule.value, rule.value + 1] {
+                let mut ratings = [1, 1000, 2000, 4000];
+                let i = "xmas".find(rule.attr).unwrap_or(0);
+                ratings[i] = value.clamp(1, 4000);
+                let part = Part::new(ratings[0], ratings[1], ratings[2], ratings[3]);
+                assert_eq!(classifier.accepts(&part), is_accepted(&input, &part));
+            }
+        }
+    }
+
+    #[test]
+    fn test_day19_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
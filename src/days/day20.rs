@@ -4,11 +4,17 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{alpha0, alpha1},
+    combinator::all_consuming,
     multi::separated_list1,
     IResult,
 };
 use num::Integer;
 
+use crate::utils::{
+    parse::{numbered_lines, ParseError},
+    rng::Rng,
+};
+
 #[derive(Debug, Clone)]
 pub enum Pulse {
     Low,
@@ -82,70 +88,30 @@ impl<'a> StateMap<'a> {
             messages,
         }
     }
-}
 
-#[derive(Debug)]
-pub struct Input<'a> {
-    module_map: HashMap<&'a str, Module<'a>>,
-}
-
-fn parse_module(input: &str) -> IResult<&str, Module> {
-    let (input, type_str) = alt((tag("%"), tag("&"), tag("broadcaster")))(input)?;
-
-    let module_type = match type_str {
-        "%" => ModuleType::FlipFlop,
-        "&" => ModuleType::Conjunction,
-        "broadcaster" => ModuleType::Broadcaster,
-        _ => unreachable!(),
-    };
-
-    let (input, mut module_name) = alpha0(input)?;
-
-    if module_name.is_empty() {
-        module_name = "broadcaster";
+    // Pushes the button once and delivers every pulse it causes. Returns the
+    // number of low and high pulses sent, including the button's own.
+    pub fn press(&mut self) -> (usize, usize) {
+        self.press_watching(|_| {})
     }
 
-    let (input, _) = tag(" -> ")(input)?;
-    let (input, dst) = separated_list1(tag(", "), alpha1)(input)?;
-
-    let module = Module {
-        module_type,
-        module_name,
-        dst,
-    };
-    Ok((input, module))
-}
-
-pub fn parse_input(input: &str) -> Input {
-    let mut module_map = HashMap::new();
-    for line in input.lines() {
-        match parse_module(line) {
-            Ok((_, module)) => module_map.insert(module.module_name, module),
-            _ => unreachable!(),
-        };
-    }
-
-    Input { module_map }
-}
-
-#[allow(unused_variables)]
-pub fn part1(input: &Input) -> Option<u64> {
-    let mut lows = 0;
-    let mut highs = 0;
-    let mut state = StateMap::new(&input.module_map);
-    for i in 0..1000 {
-        state.messages.push_back(Message {
+    // As `press`, also handing `watch` each message as it's delivered.
+    pub fn press_watching(&mut self, mut watch: impl FnMut(&Message<'a>)) -> (usize, usize) {
+        let mut lows = 0;
+        let mut highs = 0;
+        self.messages.push_back(Message {
             src: "button",
             dst: &["broadcaster"],
             pulse: Pulse::Low,
         });
         lows += 1;
 
-        while let Some(message) = state.messages.pop_front() {
+        while let Some(message) = self.messages.pop_front() {
+            watch(&message);
             let dst_modules = message
                 .dst
                 .iter()
-                .filter_map(|&dst| state.module_map.get(dst));
+                .filter_map(|&dst| self.module_map.get(dst));
             for module in dst_modules {
                 match module.module_type {
                     ModuleType::Broadcaster => {
@@ -160,7 +126,7 @@ pub fn part1(input: &Input) -> Option<u64> {
                             Pulse::High => highs += new_message.dst.len(),
                         }
 
-                        state.messages.push_back(Message {
+                        self.messages.push_back(Message {
                             src: module.module_name,
                             dst: &module.dst,
                             pulse: new_message.pulse.clone(),
@@ -171,8 +137,7 @@ pub fn part1(input: &Input) -> Option<u64> {
                             continue;
                         }
 
-                        let module_state =
-                            state.flipflop_state.get_mut(module.module_name).unwrap();
+                        let module_state = self.flipflop_state.get_mut(module.module_name).unwrap();
                         match module_state {
                             Pulse::Low => {
                                 *module_state = Pulse::High;
@@ -193,14 +158,14 @@ pub fn part1(input: &Input) -> Option<u64> {
                             Pulse::High => highs += new_message.dst.len(),
                         }
 
-                        state.messages.push_back(Message {
+                        self.messages.push_back(Message {
                             src: module.module_name,
                             dst: &module.dst,
                             pulse: new_message.pulse.clone(),
                         });
                     }
                     ModuleType::Conjunction => {
-                        let inner_map = state.conj_state.get_mut(module.module_name).unwrap();
+                        let inner_map = self.conj_state.get_mut(module.module_name).unwrap();
                         inner_map
                             .entry(message.src)
                             .and_modify(|pulse| *pulse = message.pulse.clone());
@@ -223,7 +188,7 @@ pub fn part1(input: &Input) -> Option<u64> {
                             Pulse::High => highs += new_message.dst.len(),
                         }
 
-                        state.messages.push_back(Message {
+                        self.messages.push_back(Message {
                             src: module.module_name,
                             dst: &module.dst,
                             pulse: new_message.pulse.clone(),
@@ -232,127 +197,228 @@ pub fn part1(input: &Input) -> Option<u64> {
                 }
             }
         }
+
+        (lows, highs)
     }
+}
 
-    Some(lows as u64 * highs as u64)
+#[derive(Debug)]
+pub struct Input<'a> {
+    module_map: HashMap<&'a str, Module<'a>>,
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &Input) -> Option<u64> {
-    let mut state = StateMap::new(&input.module_map);
-    let mut i: u64 = 0;
-    let mut cycle_counter = HashMap::new();
-    let mut cycles = HashMap::new();
-    loop {
-        i += 1;
-        state.messages.push_back(Message {
-            src: "button",
-            dst: &["broadcaster"],
-            pulse: Pulse::Low,
-        });
+fn parse_module(input: &str) -> IResult<&str, Module> {
+    let (input, type_str) = alt((tag("%"), tag("&"), tag("broadcaster")))(input)?;
 
-        while let Some(message) = state.messages.pop_front() {
-            let dst_modules = message
-                .dst
-                .iter()
-                .filter_map(|&dst| state.module_map.get(dst));
-            for module in dst_modules {
-                match module.module_type {
-                    ModuleType::Broadcaster => {
-                        let new_message = Message {
-                            src: module.module_name,
-                            dst: &module.dst,
-                            pulse: message.pulse.clone(),
-                        };
+    let module_type = match type_str {
+        "%" => ModuleType::FlipFlop,
+        "&" => ModuleType::Conjunction,
+        "broadcaster" => ModuleType::Broadcaster,
+        _ => unreachable!(),
+    };
 
-                        state.messages.push_back(Message {
-                            src: module.module_name,
-                            dst: &module.dst,
-                            pulse: new_message.pulse.clone(),
-                        });
-                    }
-                    ModuleType::FlipFlop => {
-                        if matches!(message.pulse, Pulse::High) {
-                            continue;
-                        }
+    let (input, mut module_name) = alpha0(input)?;
 
-                        let module_state =
-                            state.flipflop_state.get_mut(module.module_name).unwrap();
-                        match module_state {
-                            Pulse::Low => {
-                                *module_state = Pulse::High;
-                            }
-                            Pulse::High => {
-                                *module_state = Pulse::Low;
-                            }
-                        }
+    if module_name.is_empty() {
+        module_name = "broadcaster";
+    }
 
-                        let new_message = Message {
-                            src: module.module_name,
-                            dst: &module.dst,
-                            pulse: module_state.clone(),
-                        };
+    let (input, _) = tag(" -> ")(input)?;
+    let (input, dst) = separated_list1(tag(", "), alpha1)(input)?;
 
-                        state.messages.push_back(Message {
-                            src: module.module_name,
-                            dst: &module.dst,
-                            pulse: new_message.pulse.clone(),
-                        });
-                    }
-                    ModuleType::Conjunction => {
-                        let inner_map = state.conj_state.get_mut(module.module_name).unwrap();
-                        inner_map
-                            .entry(message.src)
-                            .and_modify(|pulse| *pulse = message.pulse.clone());
+    let module = Module {
+        module_type,
+        module_name,
+        dst,
+    };
+    Ok((input, module))
+}
 
-                        if module.module_name == "tj" && matches!(message.pulse, Pulse::High) {
-                            if !cycles.contains_key(message.src) {
-                                match cycle_counter.get(message.src) {
-                                    None => cycle_counter.insert(message.src, i),
-                                    Some(u) => cycles.insert(message.src, i - u),
-                                };
-                            }
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
+    let mut module_map = HashMap::new();
+    for (n, line) in numbered_lines(input) {
+        let module = match all_consuming(parse_module)(line) {
+            Ok((_, module)) => module,
+            Err(_) => return Err(ParseError::new(n, "expected a module")),
+        };
+        if module_map.insert(module.module_name, module).is_some() {
+            return Err(ParseError::new(n, "module defined twice"));
+        }
+    }
 
-                            if cycles.len() == inner_map.len() {
-                                let lcm = cycles.values().fold(1, |acc, v| acc.lcm(v));
+    Ok(Input { module_map })
+}
 
-                                return Some(lcm);
-                            }
-                        }
+// Writes the module network as a Graphviz digraph. With `presses`, flip-flops
+// are filled in green if they're on after that many button presses.
+pub fn to_dot(input: &Input, presses: Option<u64>) -> String {
+    let mut state = StateMap::new(&input.module_map);
+    for _ in 0..presses.unwrap_or(0) {
+        state.press();
+    }
 
-                        let all_high = inner_map
-                            .values()
-                            .all(|pulse| matches!(*pulse, Pulse::High));
-                        let pulse_value = match all_high {
-                            true => Pulse::Low,
-                            false => Pulse::High,
-                        };
-                        let new_message = Message {
-                            src: module.module_name,
-                            dst: &module.dst,
-                            pulse: pulse_value,
-                        };
+    let mut names: Vec<&str> = input.module_map.keys().copied().collect();
+    names.sort();
+    let mut sinks: Vec<&str> = input
+        .module_map
+        .values()
+        .flat_map(|module| module.dst.iter().copied())
+        .filter(|dst| !input.module_map.contains_key(dst))
+        .collect();
+    sinks.sort();
+    sinks.dedup();
+
+    let mut out = String::from("digraph modules {\n");
+    out.push_str("    button [shape=plaintext];\n");
+    for &name in &names {
+        let module = &input.module_map[name];
+        let (shape, label) = match module.module_type {
+            ModuleType::Broadcaster => ("doubleoctagon", name.to_string()),
+            ModuleType::FlipFlop => ("box", format!("%{}", name)),
+            ModuleType::Conjunction => ("invtriangle", format!("&{}", name)),
+        };
+        let fill = match state.flipflop_state.get(name) {
+            Some(Pulse::High) if presses.is_some() => ", style=filled, fillcolor=palegreen",
+            _ => "",
+        };
+        out.push_str(&format!(
+            "    {} [shape={}, label=\"{}\"{}];\n",
+            name, shape, label, fill
+        ));
+    }
+    for &name in &sinks {
+        out.push_str(&format!("    {} [shape=doublecircle];\n", name));
+    }
 
-                        state.messages.push_back(Message {
-                            src: module.module_name,
-                            dst: &module.dst,
-                            pulse: new_message.pulse.clone(),
-                        });
-                    }
-                }
+    out.push_str("    button -> broadcaster;\n");
+    for &name in &names {
+        for dst in &input.module_map[name].dst {
+            out.push_str(&format!("    {} -> {};\n", name, dst));
+        }
+    }
+    if let Some(presses) = presses {
+        out.push_str(&format!(
+            "    label=\"flip-flop states after {} presses\";\n",
+            presses
+        ));
+    }
+    out.push_str("}\n");
+
+    out
+}
+
+// Four binary counters like the real network's. Each is a chain of flip-flops
+// counting button presses, with a conjunction that reads the bits set in its
+// target, resets the chain once they're all on, and then pulses through an
+// inverter into the conjunction feeding rx.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let bits = (size / 4).clamp(3, 12);
+    let mut names = (0..26 * 26)
+        .map(|k| {
+            format!(
+                "{}{}",
+                char::from(b'a' + (k / 26) as u8),
+                char::from(b'a' + (k % 26) as u8)
+            )
+        })
+        .filter(|name| name != "rx");
+    let mut next_name = || names.next().unwrap();
+
+    let feeder = next_name();
+    let mut lines = vec![format!("&{} -> rx", feeder)];
+    let mut starts = Vec::new();
+    for _ in 0..4 {
+        // The lowest and highest bits are always set.
+        let target = rng.range(1 << (bits - 1)..1 << bits) as usize | 1;
+        let chain: Vec<String> = (0..bits).map(|_| next_name()).collect();
+        let (hub, inverter) = (next_name(), next_name());
+
+        let mut hub_dst = vec![chain[0].clone()];
+        for (i, flip_flop) in chain.iter().enumerate() {
+            let mut dst = Vec::new();
+            if i + 1 < bits {
+                dst.push(chain[i + 1].clone());
+            }
+            match target >> i & 1 {
+                1 => dst.push(hub.clone()),
+                _ => hub_dst.push(flip_flop.clone()),
             }
+            lines.push(format!("%{} -> {}", flip_flop, dst.join(", ")));
         }
+        hub_dst.push(inverter.clone());
+        lines.push(format!("&{} -> {}", hub, hub_dst.join(", ")));
+        lines.push(format!("&{} -> {}", inverter, feeder));
+        starts.push(chain[0].clone());
     }
+    lines.push(format!("broadcaster -> {}", starts.join(", ")));
+    rng.shuffle(&mut lines);
+
+    lines.join("\n") + "\n"
+}
+
+#[allow(unused_variables)]
+pub fn part1(input: &Input) -> Option<u64> {
+    let mut lows = 0;
+    let mut highs = 0;
+    let mut state = StateMap::new(&input.module_map);
+    for i in 0..1000 {
+        let (l, h) = state.press();
+        lows += l;
+        highs += h;
+    }
+
+    Some(lows as u64 * highs as u64)
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &Input) -> Option<u64> {
+    // rx only gets a low pulse from the conjunction feeding it once all that
+    // conjunction's inputs have just sent high. Each input does so on its own
+    // cycle, so the answer is where those cycles line up.
+    const MAX_PRESSES: u64 = 1 << 20;
+    let feeder = input
+        .module_map
+        .values()
+        .find(|module| module.dst.contains(&"rx"))?;
+    if !matches!(feeder.module_type, ModuleType::Conjunction) {
+        return None;
+    }
+
+    let mut state = StateMap::new(&input.module_map);
+    let inputs = state.conj_state.get(feeder.module_name)?.len();
+    let mut cycle_counter = HashMap::new();
+    let mut cycles = HashMap::new();
+    for i in 1..=MAX_PRESSES {
+        state.press_watching(|message| {
+            if !message.dst.contains(&feeder.module_name)
+                || !matches!(message.pulse, Pulse::High)
+                || cycles.contains_key(message.src)
+            {
+                return;
+            }
+            match cycle_counter.get(message.src) {
+                None => cycle_counter.insert(message.src, i),
+                Some(u) => cycles.insert(message.src, i - u),
+            };
+        });
+
+        if cycles.len() == inputs {
+            return Some(cycles.values().fold(1, |acc, v| acc.lcm(v)));
+        }
+    }
+
+    None
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day20/test.txt");
     const TEST_INPUT2: &str = include_str!("../../input/day20/test2.txt");
     #[test]
     fn test_day20_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -361,7 +427,7 @@ pub mod tests {
 
     #[test]
     fn test_day20_part1_test2() {
-        let input = parse_input(TEST_INPUT2);
+        let input = parse_input(TEST_INPUT2).unwrap();
 
         let resp = part1(&input);
 
@@ -370,10 +436,36 @@ pub mod tests {
 
     #[test]
     fn test_day20_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, None);
     }
+
+    #[test]
+    fn test_day20_to_dot() {
+        let input = parse_input(TEST_INPUT2).unwrap();
+
+        let dot = to_dot(&input, None);
+        assert!(dot.contains("    a [shape=box, label=\"%a\"];\n"));
+        assert!(dot.contains("    inv [shape=invtriangle, label=\"&inv\"];\n"));
+        assert!(dot.contains("    output [shape=doublecircle];\n"));
+        assert!(dot.contains("    con -> output;\n"));
+        assert!(!dot.contains("palegreen"));
+
+        // The first press leaves a and b on, the second turns a off again.
+        let dot = to_dot(&input, Some(1));
+        assert!(dot.contains("    a [shape=box, label=\"%a\", style=filled, fillcolor=palegreen];"));
+        assert!(dot.contains("    b [shape=box, label=\"%b\", style=filled, fillcolor=palegreen];"));
+        let dot = to_dot(&input, Some(2));
+        assert!(dot.contains("    a [shape=box, label=\"%a\"];"));
+        assert!(dot.contains("    b [shape=box, label=\"%b\", style=filled, fillcolor=palegreen];"));
+    }
+
+    #[test]
+    fn test_day20_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
@@ -1,5 +1,10 @@
 use std::collections::HashSet;
 
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    rng::Rng,
+};
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct Brick {
     xlow: i32,
@@ -12,18 +17,30 @@ pub struct Brick {
     supported_by: Vec<usize>,
 }
 
-pub fn parse_input(input: &str) -> Vec<Brick> {
-    let mut bricks = Vec::new();
-    for line in input.lines() {
-        let splits = line.split(&['~', ',']);
-        let mut nums = splits.map(|n| n.parse().unwrap());
+// Bounds on the coordinates, keeping the height map `settle` allocates for
+// the x and y extent small.
+const MAX_XY: i32 = 1000;
+const MAX_Z: i32 = 1000000;
 
-        let xlow = nums.next().unwrap();
-        let ylow = nums.next().unwrap();
-        let zlow = nums.next().unwrap();
-        let xhigh = nums.next().unwrap();
-        let yhigh = nums.next().unwrap();
-        let zhigh = nums.next().unwrap();
+pub fn parse_input(input: &str) -> Result<Vec<Brick>, ParseError> {
+    let mut bricks = Vec::new();
+    for (n, line) in numbered_lines(input) {
+        let nums: Vec<i32> = line
+            .split(&['~', ','])
+            .map(|s| number(n, s))
+            .collect::<Result<_, _>>()?;
+        let &[xlow, ylow, zlow, xhigh, yhigh, zhigh] = nums.as_slice() else {
+            return Err(ParseError::new(n, "expected two corners"));
+        };
+        if xlow > xhigh || ylow > yhigh || zlow > zhigh {
+            return Err(ParseError::new(n, "corners out of order"));
+        }
+        if xlow < 0 || ylow < 0 || xhigh >= MAX_XY || yhigh >= MAX_XY {
+            return Err(ParseError::new(n, "brick outside the area"));
+        }
+        if zlow < 1 || zhigh >= MAX_Z {
+            return Err(ParseError::new(n, "brick below the ground or too high"));
+        }
 
         let brick = Brick {
             xlow,
@@ -37,68 +54,119 @@ pub fn parse_input(input: &str) -> Vec<Brick> {
         };
         bricks.push(brick);
     }
+
+    Ok(settle(bricks))
+}
+
+// Drops the bricks lowest first, keeping a height map of the top brick in
+// every (x, y) column. A brick lands one above the highest column under it,
+// and is supported by whichever bricks are on top of the columns at exactly
+// that height. The result stays ordered so supporters come before the bricks
+// they hold up.
+pub fn settle(mut bricks: Vec<Brick>) -> Vec<Brick> {
     bricks.sort_by_key(|brick| brick.zlow);
 
-    let mut fallen_bricks: Vec<Brick> = Vec::new();
-    for brick in bricks.iter() {
-        let stop = fallen_bricks
-            .iter()
-            .filter(|b| b.zhigh < brick.zlow)
-            .filter(|b| {
-                brick.xlow <= b.xhigh
-                    && brick.xhigh >= b.xlow
-                    && brick.ylow <= b.yhigh
-                    && brick.yhigh >= b.ylow
+    let xmin = bricks.iter().map(|b| b.xlow).min().unwrap_or(0);
+    let xmax = bricks.iter().map(|b| b.xhigh).max().unwrap_or(0);
+    let ymin = bricks.iter().map(|b| b.ylow).min().unwrap_or(0);
+    let ymax = bricks.iter().map(|b| b.yhigh).max().unwrap_or(0);
+    let width = (xmax - xmin + 1) as usize;
+    let depth = (ymax - ymin + 1) as usize;
+
+    // (height, brick) per column, with the ground at height 0.
+    let mut tops: Vec<(i32, Option<usize>)> = vec![(0, None); width * depth];
+    for i in 0..bricks.len() {
+        let brick = &bricks[i];
+        let columns: Vec<usize> = (brick.xlow..=brick.xhigh)
+            .flat_map(|x| {
+                (brick.ylow..=brick.yhigh)
+                    .map(move |y| (y - ymin) as usize * width + (x - xmin) as usize)
             })
-            .filter(|&b| brick != b)
-            .max_by_key(|b| b.zhigh);
+            .collect();
 
-        let new_zlow = match stop {
-            None => 1,
-            Some(b) => b.zhigh + 1,
-        };
-        let new_zhigh = brick.zhigh - brick.zlow + new_zlow;
-        fallen_bricks.push(Brick {
-            xlow: brick.xlow,
-            xhigh: brick.xhigh,
-            ylow: brick.ylow,
-            yhigh: brick.yhigh,
-            zlow: new_zlow,
-            zhigh: new_zhigh,
-            supports: vec![],
-            supported_by: vec![],
-        });
-    }
-    let mut bricks = fallen_bricks;
-    bricks.sort_by_key(|b| b.zlow);
+        let floor = columns.iter().map(|&c| tops[c].0).max().unwrap();
+        let mut supported_by: Vec<usize> = columns
+            .iter()
+            .filter(|&&c| tops[c].0 == floor)
+            .filter_map(|&c| tops[c].1)
+            .collect();
+        supported_by.sort();
+        supported_by.dedup();
 
-    for i in 0..bricks.len() {
-        for j in 0..bricks.len() {
-            if bricks[j].zlow > bricks[i].zhigh + 1 {
-                break;
-            }
+        let height = brick.zhigh - brick.zlow;
+        let zlow = floor + 1;
+        let zhigh = zlow + height;
+        for &c in &columns {
+            tops[c] = (zhigh, Some(i));
+        }
+        for &j in &supported_by {
+            bricks[j].supports.push(i);
+        }
 
-            if bricks[i].xhigh < bricks[j].xlow
-                || bricks[i].xlow > bricks[j].xhigh
-                || bricks[i].yhigh < bricks[j].ylow
-                || bricks[i].ylow > bricks[j].yhigh
-            {
-                // Don't touch horizontally
-                continue;
-            }
+        let brick = &mut bricks[i];
+        brick.zlow = zlow;
+        brick.zhigh = zhigh;
+        brick.supported_by = supported_by;
+    }
 
-            if bricks[i].zhigh + 1 == bricks[j].zlow {
-                bricks[i].supports.push(j);
+    bricks
+}
+
+// Stacks random bricks up to three cubes long in a small area, a few
+// per level so that some start out at the same height.
+pub fn generate_bricks(rng: &mut Rng, count: usize, size: i32) -> Vec<Brick> {
+    let mut occupied = HashSet::new();
+    let mut bricks = Vec::new();
+    let mut z = 1;
+    while bricks.len() < count {
+        for _ in 0..3 {
+            let (xlow, ylow) = (rng.range(0..size as i64), rng.range(0..size as i64));
+            let len = rng.range(0..3) as i32;
+            let (mut xhigh, mut yhigh, mut zhigh) = (xlow as i32, ylow as i32, z);
+            match rng.below(3) {
+                0 => xhigh = (xhigh + len).min(size - 1),
+                1 => yhigh = (yhigh + len).min(size - 1),
+                _ => zhigh += len,
             }
-            if bricks[i].zlow == bricks[j].zhigh + 1 {
-                bricks[i].supported_by.push(j);
+            let cells: Vec<(i32, i32, i32)> = (xlow as i32..=xhigh)
+                .flat_map(|x| (ylow as i32..=yhigh).map(move |y| (x, y)))
+                .flat_map(|(x, y)| (z..=zhigh).map(move |z| (x, y, z)))
+                .collect();
+            if cells.iter().any(|c| occupied.contains(c)) {
+                continue;
             }
+            occupied.extend(cells);
+            bricks.push(Brick {
+                xlow: xlow as i32,
+                xhigh,
+                ylow: ylow as i32,
+                yhigh,
+                zlow: z,
+                zhigh,
+                supports: vec![],
+                supported_by: vec![],
+            });
         }
+        z += 1 + rng.range(0..3) as i32;
     }
+    bricks.truncate(count);
 
     bricks
 }
 
+// Bricks stacked up over a 10x10 area like the real snapshot.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let mut out = String::new();
+    for brick in generate_bricks(rng, size, 10) {
+        out.push_str(&format!(
+            "{},{},{}~{},{},{}\n",
+            brick.xlow, brick.ylow, brick.zlow, brick.xhigh, brick.yhigh, brick.zhigh
+        ));
+    }
+
+    out
+}
+
 #[allow(unused_variables)]
 pub fn part1(input: &[Brick]) -> Option<u64> {
     let mut sum = 0;
@@ -115,34 +183,159 @@ pub fn part1(input: &[Brick]) -> Option<u64> {
     Some(sum)
 }
 
-#[allow(unused_variables)]
-pub fn part2(input: &[Brick]) -> Option<u64> {
-    let mut sum = 0;
-    for i in 0..input.len() {
-        let mut seen = HashSet::new();
-        let mut to_process = vec![i];
-        seen.insert(i);
-
-        while let Some(idx) = to_process.pop() {
-            for &j in &input[idx].supports {
-                if input[j].supported_by.iter().all(|k| seen.contains(k)) && seen.insert(j) {
-                    to_process.push(j);
-                    sum += 1;
-                }
+// For every brick, how many others fall if it's removed. A brick falls once
+// every path down to the ground goes through the removed one, i.e. when the
+// removed brick dominates it in the support graph rooted at the ground. The
+// bricks are sorted by height so supporters always come first, which lets
+// each immediate dominator be found as the LCA of the brick's supporters in
+// the tree built so far. The answer is then the size of each subtree.
+pub fn chain_reactions(bricks: &[Brick]) -> Vec<usize> {
+    // Node 0 is the ground, brick i is node i + 1.
+    let n = bricks.len() + 1;
+    let levels = (usize::BITS - n.leading_zeros()) as usize;
+    let mut up = vec![vec![0; n]; levels.max(1)];
+    let mut depth = vec![0; n];
+
+    let lca = |up: &Vec<Vec<usize>>, depth: &Vec<usize>, mut a: usize, mut b: usize| {
+        if depth[a] < depth[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        for k in (0..up.len()).rev() {
+            if depth[a] - depth[b] >= 1 << k {
+                a = up[k][a];
             }
         }
+        if a == b {
+            return a;
+        }
+        for k in (0..up.len()).rev() {
+            if up[k][a] != up[k][b] {
+                a = up[k][a];
+                b = up[k][b];
+            }
+        }
+        up[0][a]
+    };
+
+    for (i, brick) in bricks.iter().enumerate() {
+        let node = i + 1;
+        let idom = brick
+            .supported_by
+            .iter()
+            .map(|&j| j + 1)
+            .reduce(|a, b| lca(&up, &depth, a, b))
+            .unwrap_or(0);
+
+        depth[node] = depth[idom] + 1;
+        up[0][node] = idom;
+        for k in 1..up.len() {
+            up[k][node] = up[k - 1][up[k - 1][node]];
+        }
     }
 
-    Some(sum)
+    let mut sizes = vec![1; n];
+    for node in (1..n).rev() {
+        sizes[up[0][node]] += sizes[node];
+    }
+
+    sizes[1..].iter().map(|size| size - 1).collect()
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BrickReport {
+    pub low: (i32, i32, i32),
+    pub high: (i32, i32, i32),
+    pub falls: usize,
+}
+
+// Where each brick came to rest and how many others fall without it.
+pub fn analyse(bricks: &[Brick]) -> Vec<BrickReport> {
+    bricks
+        .iter()
+        .zip(chain_reactions(bricks))
+        .map(|(brick, falls)| BrickReport {
+            low: (brick.xlow, brick.ylow, brick.zlow),
+            high: (brick.xhigh, brick.yhigh, brick.zhigh),
+            falls,
+        })
+        .collect()
+}
+
+// Corners of a unit cube as (dx, dy, dz), indexed by dx + 2 * dy + 4 * dz,
+// and its faces wound anticlockwise when seen from outside.
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+];
+
+// ASCII PLY mesh of the settled bricks, one box each. Bricks that are safe to
+// disintegrate are green and load bearing ones red.
+pub fn to_ply(bricks: &[Brick]) -> String {
+    let reports = analyse(bricks);
+
+    let mut out = String::from("ply\nformat ascii 1.0\n");
+    out.push_str("comment green bricks are safe to disintegrate, red ones are load bearing\n");
+    out.push_str(&format!("element vertex {}\n", 8 * reports.len()));
+    for prop in [
+        "float x",
+        "float y",
+        "float z",
+        "uchar red",
+        "uchar green",
+        "uchar blue",
+    ] {
+        out.push_str(&format!("property {}\n", prop));
+    }
+    out.push_str(&format!("element face {}\n", 6 * reports.len()));
+    out.push_str("property list uchar int vertex_indices\nend_header\n");
+
+    for report in &reports {
+        let colour = match report.falls {
+            0 => (60, 180, 75),
+            _ => (220, 50, 50),
+        };
+        // Each cube of the brick spans one unit, so the far side is high + 1.
+        let (x0, y0, z0) = report.low;
+        let (x1, y1, z1) = (report.high.0 + 1, report.high.1 + 1, report.high.2 + 1);
+        for corner in 0..8 {
+            let x = if corner & 1 == 0 { x0 } else { x1 };
+            let y = if corner & 2 == 0 { y0 } else { y1 };
+            let z = if corner & 4 == 0 { z0 } else { z1 };
+            out.push_str(&format!(
+                "{} {} {} {} {} {}\n",
+                x, y, z, colour.0, colour.1, colour.2
+            ));
+        }
+    }
+    for i in 0..reports.len() {
+        for face in CUBE_FACES {
+            let [a, b, c, d] = face.map(|corner| 8 * i + corner);
+            out.push_str(&format!("4 {} {} {} {}\n", a, b, c, d));
+        }
+    }
+
+    out
+}
+
+#[allow(unused_variables)]
+pub fn part2(input: &[Brick]) -> Option<u64> {
+    let sum: usize = chain_reactions(input).iter().sum();
+
+    Some(sum as u64)
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day22/test.txt");
     #[test]
     fn test_day22_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -151,10 +344,182 @@ pub mod tests {
 
     #[test]
     fn test_day22_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, Some(7));
     }
+
+    // The original settle, scanning every fallen brick for each new one.
+    fn settle_by_scan(mut bricks: Vec<Brick>) -> Vec<Brick> {
+        bricks.sort_by_key(|brick| brick.zlow);
+
+        let mut fallen_bricks: Vec<Brick> = Vec::new();
+        for brick in bricks.iter() {
+            let stop = fallen_bricks
+                .iter()
+                .filter(|b| b.zhigh < brick.zlow)
+                .filter(|b| {
+                    brick.xlow <= b.xhigh
+                        && brick.xhigh >= b.xlow
+                        && brick.ylow <= b.yhigh
+                        && brick.yhigh >= b.ylow
+                })
+                .filter(|&b| brick != b)
+                .max_by_key(|b| b.zhigh);
+
+            let new_zlow = match stop {
+                None => 1,
+                Some(b) => b.zhigh + 1,
+            };
+            let new_zhigh = brick.zhigh - brick.zlow + new_zlow;
+            fallen_bricks.push(Brick {
+                xlow: brick.xlow,
+                xhigh: brick.xhigh,
+                ylow: brick.ylow,
+                yhigh: brick.yhigh,
+                zlow: new_zlow,
+                zhigh: new_zhigh,
+                supports: vec![],
+                supported_by: vec![],
+            });
+        }
+        let mut bricks = fallen_bricks;
+        bricks.sort_by_key(|b| b.zlow);
+
+        for i in 0..bricks.len() {
+            for j in 0..bricks.len() {
+                if bricks[j].zlow > bricks[i].zhigh + 1 {
+                    break;
+                }
+
+                if bricks[i].xhigh < bricks[j].xlow
+                    || bricks[i].xlow > bricks[j].xhigh
+                    || bricks[i].yhigh < bricks[j].ylow
+                    || bricks[i].ylow > bricks[j].yhigh
+                {
+                    // Don't touch horizontally
+                    continue;
+                }
+
+                if bricks[i].zhigh + 1 == bricks[j].zlow {
+                    bricks[i].supports.push(j);
+                }
+                if bricks[i].zlow == bricks[j].zhigh + 1 {
+                    bricks[i].supported_by.push(j);
+                }
+            }
+        }
+
+        bricks
+    }
+
+    // Each brick's resting place with the places of the bricks around it,
+    // which doesn't depend on the order the bricks end up in.
+    fn describe(bricks: &[Brick]) -> Vec<String> {
+        let place = |b: &Brick| (b.xlow, b.ylow, b.zlow, b.xhigh, b.yhigh, b.zhigh);
+        let mut described: Vec<String> = bricks
+            .iter()
+            .map(|b| {
+                let mut supports: Vec<_> = b.supports.iter().map(|&j| place(&bricks[j])).collect();
+                let mut supported_by: Vec<_> =
+                    b.supported_by.iter().map(|&j| place(&bricks[j])).collect();
+                supports.sort();
+                supported_by.sort();
+                format!("{:?} {:?} {:?}", place(b), supports, supported_by)
+            })
+            .collect();
+        described.sort();
+        described
+    }
+
+    #[test]
+    fn test_day22_settle() {
+        let mut rng = Rng::new(22);
+        for _ in 0..20 {
+            let bricks = generate_bricks(&mut rng, 300, 6);
+            let copy = bricks
+                .iter()
+                .map(|b| Brick {
+                    supports: vec![],
+                    supported_by: vec![],
+                    ..*b
+                })
+                .collect();
+            assert_eq!(describe(&settle(bricks)), describe(&settle_by_scan(copy)));
+        }
+
+        let bricks = settle(generate_bricks(&mut rng, 30000, 10));
+        assert_eq!(bricks.len(), 30000);
+        assert!(bricks.iter().all(|b| b
+            .supported_by
+            .iter()
+            .all(|&j| bricks[j].zhigh + 1 == b.zlow)));
+    }
+
+    // Removes each brick in turn and follows the falling bricks upwards.
+    fn chain_reactions_by_search(bricks: &[Brick]) -> Vec<usize> {
+        let mut counts = Vec::new();
+        for i in 0..bricks.len() {
+            let mut fallen = vec![false; bricks.len()];
+            let mut to_process = vec![i];
+            fallen[i] = true;
+
+            let mut count = 0;
+            while let Some(idx) = to_process.pop() {
+                for &j in &bricks[idx].supports {
+                    if !fallen[j] && bricks[j].supported_by.iter().all(|&k| fallen[k]) {
+                        fallen[j] = true;
+                        to_process.push(j);
+                        count += 1;
+                    }
+                }
+            }
+            counts.push(count);
+        }
+
+        counts
+    }
+
+    #[test]
+    fn test_day22_chain_reactions() {
+        let input = parse_input(TEST_INPUT).unwrap();
+
+        let report = analyse(&input);
+        let falls: Vec<usize> = report.iter().map(|r| r.falls).collect();
+        assert_eq!(falls, vec![6, 0, 0, 0, 0, 1, 0]);
+        assert_eq!(report[6].low, (1, 1, 5));
+        assert_eq!(report[6].high, (1, 1, 6));
+
+        let input = parse_input(include_str!("../../input/day22/input.txt")).unwrap();
+        assert_eq!(chain_reactions(&input), chain_reactions_by_search(&input));
+    }
+
+    #[test]
+    fn test_day22_to_ply() {
+        let input = parse_input(TEST_INPUT).unwrap();
+
+        let ply = to_ply(&input);
+        let body: Vec<&str> = ply.split("end_header\n").nth(1).unwrap().lines().collect();
+        assert!(ply.contains("element vertex 56\n"));
+        assert!(ply.contains("element face 42\n"));
+        assert_eq!(body.len(), 56 + 42);
+
+        // Brick A rests on the ground and brings everything down without it.
+        assert_eq!(body[0], "1 0 1 220 50 50");
+        assert_eq!(body[7], "2 3 2 220 50 50");
+        let safe = body[..56]
+            .iter()
+            .filter(|v| v.ends_with("60 180 75"))
+            .count();
+        assert_eq!(safe / 8, part1(&input).unwrap() as usize);
+        assert_eq!(body[56], "4 0 2 3 1");
+    }
+
+    #[test]
+    fn test_day22_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
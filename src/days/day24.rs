@@ -1,3 +1,8 @@
+use crate::utils::{
+    parse::{number, numbered_lines, ParseError},
+    rng::Rng,
+};
+
 const LOW: f32 = 200000000000000f32;
 const HIGH: f32 = 400000000000000f32;
 
@@ -74,29 +79,27 @@ pub fn solve_system_part1(pv1: &PosVel3, pv2: &PosVel3) -> Result<(f32, f32), Er
     Ok((x, y))
 }
 
-pub fn parse_input(input: &str) -> Input {
-    let data = input
-        .lines()
-        .map(|line| {
-            let mut split = line.split(&[',', '@']).map(|s| s.trim().parse().unwrap());
-
-            let posx = split.next().unwrap();
-            let posy = split.next().unwrap();
-            let posz = split.next().unwrap();
-            let velx = split.next().unwrap();
-            let vely = split.next().unwrap();
-            let velz = split.next().unwrap();
-
-            PosVel3 {
+pub fn parse_input(input: &str) -> Result<Input, ParseError> {
+    let data = numbered_lines(input)
+        .map(|(n, line)| {
+            let nums: Vec<f32> = line
+                .split(&[',', '@'])
+                .map(|s| number(n, s.trim()))
+                .collect::<Result<_, _>>()?;
+            let &[posx, posy, posz, velx, vely, velz] = nums.as_slice() else {
+                return Err(ParseError::new(n, "expected a position and a velocity"));
+            };
+
+            Ok(PosVel3 {
                 posx,
                 posy,
                 posz,
                 velx,
                 vely,
                 velz,
-            }
+            })
         })
-        .collect();
+        .collect::<Result<_, _>>()?;
 
     let mut input = Input {
         data,
@@ -108,7 +111,24 @@ pub fn parse_input(input: &str) -> Input {
         input.low_bound = 7f32;
         input.high_bound = 27f32;
     }
-    input
+    Ok(input)
+}
+
+// Hailstones spread through the same box as the real ones. Anything under ten
+// stones is taken to be the example and checked against its tiny test area,
+// so there are always at least ten.
+pub fn generate(rng: &mut Rng, size: usize) -> String {
+    let mut out = String::new();
+    for _ in 0..size.max(10) {
+        let [px, py, pz] = [(); 3].map(|_| rng.range(100000000000000..500000000000000));
+        let [vx, vy, vz] = [(); 3].map(|_| rng.range(1..500) * *rng.choose(&[-1, 1]));
+        out.push_str(&format!(
+            "{}, {}, {} @ {}, {}, {}\n",
+            px, py, pz, vx, vy, vz
+        ));
+    }
+
+    out
 }
 
 #[allow(unused_variables)]
@@ -139,10 +159,11 @@ pub fn part2(input: &Input) -> Option<u64> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day24/test.txt");
     #[test]
     fn test_day24_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(&input);
 
@@ -151,10 +172,16 @@ pub mod tests {
 
     #[test]
     fn test_day24_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, None);
     }
+
+    #[test]
+    fn test_day24_fuzz_parser() {
+        let generated = generate(&mut Rng::new(0), 10);
+        assert_parser_total(&[TEST_INPUT, &generated], |s| parse_input(s).map(drop));
+    }
 }
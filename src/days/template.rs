@@ -1,5 +1,7 @@
-pub fn parse_input(input: &str) -> &str {
-    input
+use crate::utils::parse::ParseError;
+
+pub fn parse_input(input: &str) -> Result<&str, ParseError> {
+    Ok(input)
 }
 
 #[allow(unused_variables)]
@@ -15,10 +17,11 @@ pub fn part2(input: &str) -> Option<u64> {
 #[cfg(test)]
 pub mod tests {
     use super::*;
+    use crate::utils::fuzz::assert_parser_total;
     const TEST_INPUT: &str = include_str!("../../input/day00/test.txt");
     #[test]
     fn test_day00_part1() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part1(input);
 
@@ -27,10 +30,15 @@ pub mod tests {
 
     #[test]
     fn test_day00_part2() {
-        let input = parse_input(TEST_INPUT);
+        let input = parse_input(TEST_INPUT).unwrap();
 
         let resp = part2(&input);
 
         assert_eq!(resp, None);
     }
+
+    #[test]
+    fn test_day00_fuzz_parser() {
+        assert_parser_total(&[TEST_INPUT], |s| parse_input(s).map(drop));
+    }
 }
@@ -2,21 +2,296 @@ pub mod days;
 pub mod solution;
 pub mod utils;
 
-use clap::Parser;
-use solution::{print_table, Solution};
+use std::{fs, io, path::PathBuf};
+
+use clap::{Parser, Subcommand};
+use solution::{input_files, print_table, Results, Solution};
+use utils::{parse::ParseError, rng::Rng};
 
 #[derive(Parser, Debug)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     day: Option<u32>,
+
+    /// Print a debug rendering of the day's input instead of solving it
+    #[arg(long, requires = "day")]
+    render: bool,
+
+    /// Read the puzzle input from this file instead of input/dayXX/input.txt
+    #[arg(long, requires = "day")]
+    input: Option<PathBuf>,
+
+    /// Run against every .txt file in input/dayXX, checking answers against
+    /// any .expected file next to it
+    #[arg(long, conflicts_with = "input")]
+    all_inputs: bool,
+
+    /// Check the day's input for problems instead of solving it
+    #[arg(long, requires = "day")]
+    lint: bool,
+
+    /// Write the day's input as a Graphviz graph to this file
+    #[arg(long, requires = "day", value_name = "FILE")]
+    dot: Option<PathBuf>,
+
+    /// Write an image of the day's input to this file (PPM, SVG or PLY)
+    #[arg(long, requires = "day", value_name = "FILE")]
+    export: Option<PathBuf>,
+
+    /// Write numbered animation frames of the day's input to this directory
+    #[arg(long, requires = "day", value_name = "DIR")]
+    frames: Option<PathBuf>,
+
+    /// Which part's instructions to export for day 18
+    #[arg(long, requires = "export", value_parser = clap::value_parser!(u32).range(1..=2))]
+    part: Option<u32>,
+
+    /// Button presses to simulate before writing the day 20 graph
+    #[arg(long, requires = "dot")]
+    presses: Option<u64>,
+
+    /// Label the day 19 graph with accepted combinations per edge
+    #[arg(long, requires = "dot")]
+    annotate: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print a random input for a day
+    Gen {
+        day: u32,
+
+        /// Roughly how many lines or items to generate
+        #[arg(long, default_value_t = 100)]
+        size: usize,
+
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+}
+
+// Parsed input for the debugging modes, which have nothing to show without it.
+fn parsed<T>(input: Result<T, ParseError>) -> T {
+    input.unwrap_or_else(|err| {
+        eprintln!("Couldn't parse input: {}", err);
+        std::process::exit(1);
+    })
+}
+
+fn render(day: u32, data: &str) -> Option<String> {
+    let rendered = match day {
+        10 => days::day10::render(&parsed(days::day10::parse_input(data))),
+        14 => days::day14::render(&parsed(days::day14::parse_input(data))),
+        13 => days::day13::render_all(&parsed(days::day13::parse_input(data))),
+        _ => return None,
+    };
+
+    Some(rendered)
+}
+
+fn lint(day: u32, data: &str) -> Option<Vec<String>> {
+    let issues = match day {
+        19 => {
+            let input = parsed(days::day19::parse_input(data));
+            days::day19::validate(&input)
+                .iter()
+                .map(|issue| issue.to_string())
+                .collect()
+        }
+        _ => return None,
+    };
+
+    Some(issues)
+}
+
+fn dot(day: u32, data: &str, args: &Args) -> Option<String> {
+    let graph = match day {
+        19 => days::day19::to_dot(&parsed(days::day19::parse_input(data)), args.annotate),
+        20 => days::day20::to_dot(&parsed(days::day20::parse_input(data)), args.presses),
+        _ => return None,
+    };
+
+    Some(graph)
+}
+
+fn export(day: u32, data: &str, args: &Args) -> Option<Vec<u8>> {
+    let image = match day {
+        10 => days::day10::to_ppm(&parsed(days::day10::parse_input(data))),
+        18 => {
+            let input = parsed(days::day18::parse_input(data));
+            let points = days::day18::vertices(&input, args.part.unwrap_or(1));
+            days::day18::to_svg(&points).into_bytes()
+        }
+        22 => days::day22::to_ply(&parsed(days::day22::parse_input(data))).into_bytes(),
+        _ => return None,
+    };
+
+    Some(image)
+}
+
+fn frames(day: u32, data: &str) -> Option<Vec<(String, Vec<u8>)>> {
+    let frames = match day {
+        14 => days::day14::ppm_frames(&parsed(days::day14::parse_input(data))),
+        _ => return None,
+    };
+
+    Some(frames)
 }
 
 fn main() {
-    let solutions = solution::all_solutions();
+    let args = Args::parse();
+
+    if let Some(Command::Gen { day, size, seed }) = args.command {
+        let name = format!("day{:02}", day);
+        let generate = solution::all_solutions()
+            .into_iter()
+            .find(|solution| solution.day == name)
+            .and_then(|solution| solution.generate);
+        match generate {
+            Some(generate) => print!("{}", generate(&mut Rng::new(seed), size)),
+            None => eprintln!("No generator for day {}", day),
+        }
+        return;
+    }
+
+    let mut solutions = solution::all_solutions();
+    if let Some(day) = args.day {
+        let name = format!("day{:02}", day);
+        solutions.retain(|solution| solution.day == name);
+        if solutions.is_empty() {
+            eprintln!("No solution for day {}", day);
+            return;
+        }
+    }
+
+    let custom_input = args
+        .input
+        .as_ref()
+        .map(|path| fs::read_to_string(path).expect("Couldn't read input file"));
+
+    if args.render {
+        let day = args.day.unwrap();
+        let data = custom_input.as_deref().unwrap_or(solutions[0].input);
+        match render(day, data) {
+            Some(rendered) => print!("{}", rendered),
+            None => eprintln!("No rendering for day {}", day),
+        }
+        return;
+    }
+
+    if args.lint {
+        let day = args.day.unwrap();
+        let data = custom_input.as_deref().unwrap_or(solutions[0].input);
+        match lint(day, data) {
+            Some(issues) if issues.is_empty() => println!("No issues"),
+            Some(issues) => issues.iter().for_each(|issue| println!("{}", issue)),
+            None => eprintln!("No lint for day {}", day),
+        }
+        return;
+    }
+
+    if let Some(path) = &args.dot {
+        let day = args.day.unwrap();
+        let data = custom_input.as_deref().unwrap_or(solutions[0].input);
+        match dot(day, data, &args) {
+            Some(graph) => fs::write(path, graph).expect("Couldn't write graph file"),
+            None => eprintln!("No graph for day {}", day),
+        }
+        return;
+    }
+
+    if let Some(path) = &args.export {
+        let day = args.day.unwrap();
+        let data = custom_input.as_deref().unwrap_or(solutions[0].input);
+        match export(day, data, &args) {
+            Some(image) => fs::write(path, image).expect("Couldn't write image file"),
+            None => eprintln!("No image export for day {}", day),
+        }
+        return;
+    }
+
+    if let Some(dir) = &args.frames {
+        let day = args.day.unwrap();
+        let data = custom_input.as_deref().unwrap_or(solutions[0].input);
+        let Some(frames) = frames(day, data) else {
+            eprintln!("No animation for day {}", day);
+            return;
+        };
+
+        fs::create_dir_all(dir).expect("Couldn't create frame directory");
+        let mut labels = String::new();
+        for (i, (label, image)) in frames.iter().enumerate() {
+            let name = format!("frame_{:04}.ppm", i);
+            fs::write(dir.join(&name), image).expect("Couldn't write frame");
+            labels.push_str(&format!("{} {}\n", name, label));
+        }
+        fs::write(dir.join("frames.txt"), labels).expect("Couldn't write frame labels");
+        return;
+    }
+
+    if args.all_inputs {
+        let mut all_results = Vec::new();
+        let mut unreadable = 0;
+        for Solution { day, func, .. } in solutions {
+            let files = match input_files(day) {
+                Ok(files) => files,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    eprintln!("{}: no input directory, skipping", day);
+                    continue;
+                }
+                Err(err) => {
+                    eprintln!("{}: couldn't read inputs: {}", day, err);
+                    unreadable += 1;
+                    continue;
+                }
+            };
+            for file in files {
+                match (func)(&file.data) {
+                    Ok(results) => all_results.push(Results {
+                        input: file.name,
+                        expected: file.expected,
+                        ..results
+                    }),
+                    Err(err) => eprintln!("{} {}: couldn't parse input: {}", day, file.name, err),
+                }
+            }
+        }
+
+        let total = all_results.len();
+        let failed = all_results
+            .iter()
+            .filter(|results| !results.mismatches().is_empty())
+            .count();
+        print_table(all_results);
+        if failed > 0 {
+            eprintln!("{} of {} inputs gave unexpected answers", failed, total);
+        }
+        if failed > 0 || unreadable > 0 {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let mut all_results = Vec::new();
-    for Solution { day, input, func } in solutions {
-        let results = (func)(input);
-        all_results.push(results);
+    for Solution {
+        day, input, func, ..
+    } in solutions
+    {
+        match (func)(custom_input.as_deref().unwrap_or(input)) {
+            Ok(results) => all_results.push(match &args.input {
+                Some(path) => Results {
+                    input: path.display().to_string(),
+                    ..results
+                },
+                None => results,
+            }),
+            Err(err) => eprintln!("{}: couldn't parse input: {}", day, err),
+        }
     }
 
-    print_table(all_results);
+    if !all_results.is_empty() {
+        print_table(all_results);
+    }
 }
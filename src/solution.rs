@@ -1,9 +1,25 @@
-use crate::days;
+use crate::{
+    days,
+    utils::{
+        parse::{number, numbered_lines, ParseError},
+        rng::Rng,
+    },
+};
 use prettytable::{row, Row, Table};
-use std::time::{Duration, Instant};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// Answers an input should give, one per part. None for a part that isn't
+// checked.
+pub type Expected = [Option<u64>; 2];
 
 pub struct Results {
     pub day: &'static str,
+    pub input: String,
+    pub expected: Option<Expected>,
     pub part1_output: Option<u64>,
     pub part2_output: Option<u64>,
     pub parse_time: Duration,
@@ -13,11 +29,46 @@ pub struct Results {
 }
 
 impl Results {
+    // Parts whose output doesn't match the expected answer, with that answer.
+    pub fn mismatches(&self) -> Vec<(u8, u64)> {
+        let Some(expected) = self.expected else {
+            return Vec::new();
+        };
+
+        [self.part1_output, self.part2_output]
+            .iter()
+            .zip(expected)
+            .enumerate()
+            .filter_map(|(i, (&output, expected))| match expected {
+                Some(answer) if output != Some(answer) => Some((i as u8 + 1, answer)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn check(&self) -> String {
+        if self.expected.is_none() {
+            return String::new();
+        }
+
+        let mismatches = self.mismatches();
+        if mismatches.is_empty() {
+            return "ok".to_string();
+        }
+        mismatches
+            .iter()
+            .map(|(part, answer)| format!("part{} expected {}", part, answer))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
     pub fn as_row(&self) -> Row {
         row![
             self.day,
+            self.input,
             self.part1_output.unwrap_or(0),
             self.part2_output.unwrap_or(0),
+            self.check(),
             format!("{:?}", self.parse_time),
             format!("{:?}", self.part1_time),
             format!("{:?}", self.part2_time),
@@ -31,8 +82,10 @@ pub fn print_table(results: Vec<Results>) {
 
     table.set_titles(row![
         "Day",
+        "Input",
         "Part1 Output",
         "Part2 Output",
+        "Check",
         "Parse Time",
         "Part1 Time",
         "Part2 Time",
@@ -45,42 +98,131 @@ pub fn print_table(results: Vec<Results>) {
     table.printstd();
 }
 
+// Output of each part asked for, in order.
+pub type Outputs = Vec<Option<u64>>;
+
 pub struct Solution {
     pub day: &'static str,
     pub input: &'static str,
-    pub func: fn(&str) -> Results,
+    pub func: fn(&str) -> Result<Results, ParseError>,
+    // Parses once, then runs the given parts in order against the same input.
+    pub run_parts: fn(&str, &[u8]) -> Result<Outputs, ParseError>,
+    // Makes a random input of roughly `size` lines or items, where the day
+    // has a generator.
+    pub generate: Option<fn(&mut Rng, usize) -> String>,
 }
 
 #[macro_export]
 macro_rules! solution {
     ($day:tt) => {
+        solution!($day, Some(days::$day::generate))
+    };
+    ($day:tt, $generate:expr) => {
         Solution {
             day: stringify!($day),
             input: include_str!(concat!("../input/", stringify!($day), "/input.txt")),
             func: |data: &str| {
                 let mut t = [Instant::now(); 4];
                 t[0] = Instant::now();
-                let input = days::$day::parse_input(data);
+                let input = days::$day::parse_input(data)?;
                 t[1] = Instant::now();
                 let part1_output = days::$day::part1(&input);
                 t[2] = Instant::now();
                 let part2_output = days::$day::part2(&input);
                 t[3] = Instant::now();
 
-                Results {
+                Ok(Results {
                     day: stringify!($day),
+                    input: "input.txt".to_string(),
+                    expected: None,
                     part1_output,
                     part2_output,
                     parse_time: t[1] - t[0],
                     part1_time: t[2] - t[1],
                     part2_time: t[3] - t[2],
                     total_time: t[3] - t[0],
-                }
+                })
             },
+            run_parts: |data: &str, parts: &[u8]| {
+                let input = days::$day::parse_input(data)?;
+                let outputs = parts
+                    .iter()
+                    .map(|part| match part {
+                        1 => days::$day::part1(&input),
+                        2 => days::$day::part2(&input),
+                        _ => unreachable!(),
+                    })
+                    .collect();
+
+                Ok(outputs)
+            },
+            generate: $generate,
         }
     };
 }
 
+pub struct InputFile {
+    pub name: String,
+    pub data: String,
+    pub expected: Option<Expected>,
+}
+
+// Reads a `.expected` file: the part 1 answer on the first line and part 2 on
+// the second, with `-` for a part that isn't checked.
+fn parse_expected(text: &str) -> Result<Expected, ParseError> {
+    let mut expected = [None; 2];
+    let mut lines = numbered_lines(text);
+    for answer in expected.iter_mut() {
+        let (n, line) = lines
+            .next()
+            .ok_or(ParseError::new(0, "expected an answer for each part"))?;
+        *answer = match line.trim() {
+            "-" => None,
+            line => Some(number(n, line)?),
+        };
+    }
+    if let Some((n, _)) = lines.find(|(_, line)| !line.trim().is_empty()) {
+        return Err(ParseError::new(n, "expected only two answers"));
+    }
+
+    Ok(expected)
+}
+
+// Every .txt file in the day's input directory, in name order, along with the
+// answers from the `.expected` file next to it if there is one. A malformed
+// `.expected` file is an `InvalidData` error.
+pub fn input_files(day: &str) -> io::Result<Vec<InputFile>> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("input")
+        .join(day);
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<_>>()?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "txt"));
+    paths.sort();
+
+    paths
+        .iter()
+        .map(|path| {
+            let expected_path = path.with_extension("expected");
+            let expected = match fs::read_to_string(&expected_path) {
+                Ok(text) => Some(parse_expected(&text).map_err(|err| {
+                    let message = format!("{}: {}", expected_path.display(), err);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                })?),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err),
+            };
+
+            Ok(InputFile {
+                name: path.file_name().unwrap().to_string_lossy().into_owned(),
+                data: fs::read_to_string(path)?,
+                expected,
+            })
+        })
+        .collect()
+}
+
 pub fn all_solutions() -> Vec<Solution> {
     vec![
         solution!(day01),
@@ -94,7 +236,7 @@ pub fn all_solutions() -> Vec<Solution> {
         solution!(day09),
         solution!(day10),
         solution!(day11),
-        solution!(day12),
+        solution!(day12, None),
         solution!(day13),
         solution!(day14),
         solution!(day15),
@@ -105,3 +247,114 @@ pub fn all_solutions() -> Vec<Solution> {
         solution!(day24),
     ]
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // Parts share one parsed input in the runner, so neither may depend on
+    // what the other (or an earlier run of itself) did to it.
+    #[test]
+    fn test_parts_are_pure() {
+        let order = [2, 1, 1, 2, 2, 1];
+        for solution in all_solutions() {
+            let part1 = (solution.run_parts)(solution.input, &[1]).unwrap()[0];
+            let part2 = (solution.run_parts)(solution.input, &[2]).unwrap()[0];
+
+            let outputs = (solution.run_parts)(solution.input, &order).unwrap();
+            for (part, output) in order.iter().zip(outputs) {
+                let expected = match part {
+                    1 => part1,
+                    _ => part2,
+                };
+                assert_eq!(output, expected, "{} part{}", solution.day, part);
+            }
+        }
+    }
+
+    // Small generated inputs must parse and be answered by every part that
+    // answers one of the day's own inputs, and the same seed must always give
+    // the same input.
+    #[test]
+    fn test_generated_inputs() {
+        for solution in all_solutions() {
+            let Some(generate) = solution.generate else {
+                continue;
+            };
+
+            let mut answered = [false; 2];
+            for file in input_files(solution.day).unwrap() {
+                let outputs = (solution.run_parts)(&file.data, &[1, 2]).unwrap();
+                for (answered, output) in answered.iter_mut().zip(outputs) {
+                    *answered |= output.is_some();
+                }
+            }
+
+            for seed in 0..5 {
+                let input = generate(&mut Rng::new(seed), 20);
+                assert_eq!(input, generate(&mut Rng::new(seed), 20));
+
+                let outputs = (solution.run_parts)(&input, &[1, 2]).unwrap();
+                for (part, (output, answered)) in outputs.iter().zip(answered).enumerate() {
+                    assert!(
+                        output.is_some() || !answered,
+                        "{} part{} seed {}",
+                        solution.day,
+                        part + 1,
+                        seed
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_expected() {
+        assert_eq!(parse_expected("142\n281\n"), Ok([Some(142), Some(281)]));
+        assert_eq!(parse_expected("-\n 281 \n\n"), Ok([None, Some(281)]));
+        assert_eq!(parse_expected("-\n-"), Ok([None, None]));
+
+        assert_eq!(
+            parse_expected("142\n28l\n"),
+            Err(ParseError::new(2, "expected a number"))
+        );
+        assert_eq!(
+            parse_expected("\n281\n"),
+            Err(ParseError::new(1, "expected a number"))
+        );
+        assert_eq!(
+            parse_expected("142\n"),
+            Err(ParseError::new(0, "expected an answer for each part"))
+        );
+        assert_eq!(
+            parse_expected("142\n281\n3\n"),
+            Err(ParseError::new(3, "expected only two answers"))
+        );
+    }
+
+    // Every input with a .expected file next to it must give those answers.
+    #[test]
+    fn test_expected_answers() {
+        for solution in all_solutions() {
+            for file in input_files(solution.day).unwrap() {
+                let Some(expected) = file.expected else {
+                    continue;
+                };
+
+                let outputs = (solution.run_parts)(&file.data, &[1, 2]).unwrap();
+                for (part, (output, expected)) in outputs.iter().zip(expected).enumerate() {
+                    if expected.is_some() {
+                        assert_eq!(
+                            *output,
+                            expected,
+                            "{} {} part{}",
+                            solution.day,
+                            file.name,
+                            part + 1
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::{collections::HashMap, hash::Hash};
+
+// Brent's algorithm. Returns (prefix length, period) for the sequence
+// start, step(start), step(step(start)), ... without storing any states.
+pub fn brent<T, F>(start: &T, step: F) -> (usize, usize)
+where
+    T: Clone + Eq,
+    F: Fn(&T) -> T,
+{
+    let mut power = 1;
+    let mut period = 1;
+    let mut tortoise = start.clone();
+    let mut hare = step(start);
+    while tortoise != hare {
+        if power == period {
+            tortoise = hare.clone();
+            power *= 2;
+            period = 0;
+        }
+        hare = step(&hare);
+        period += 1;
+    }
+
+    let mut tortoise = start.clone();
+    let mut hare = start.clone();
+    for _ in 0..period {
+        hare = step(&hare);
+    }
+
+    let mut prefix = 0;
+    while tortoise != hare {
+        tortoise = step(&tortoise);
+        hare = step(&hare);
+        prefix += 1;
+    }
+
+    (prefix, period)
+}
+
+// Walks the sequence until a state repeats, returning every state seen along
+// with the index the repeated state was first seen at.
+fn walk<T, F>(start: T, step: F, limit: usize) -> (Vec<T>, Option<usize>)
+where
+    T: Clone + Hash + Eq,
+    F: Fn(&T) -> T,
+{
+    let mut seen = HashMap::new();
+    let mut states = vec![start];
+    loop {
+        let curr = states.last().unwrap();
+        if let Some(&first) = seen.get(curr) {
+            states.pop();
+            return (states, Some(first));
+        }
+        if states.len() > limit {
+            return (states, None);
+        }
+        seen.insert(curr.clone(), states.len() - 1);
+        let next = step(curr);
+        states.push(next);
+    }
+}
+
+// Hash based cycle detection. Stores every state, but only steps through the
+// sequence once. Returns (prefix length, period).
+pub fn find_cycle<T, F>(start: &T, step: F) -> (usize, usize)
+where
+    T: Clone + Hash + Eq,
+    F: Fn(&T) -> T,
+{
+    let (states, first) = walk(start.clone(), step, usize::MAX);
+    let prefix = first.unwrap();
+
+    (prefix, states.len() - prefix)
+}
+
+// Returns the state after applying step n times, skipping ahead once a cycle
+// has been found.
+pub fn nth_state<T, F>(state: T, step: F, n: usize) -> T
+where
+    T: Clone + Hash + Eq,
+    F: Fn(&T) -> T,
+{
+    let (mut states, first) = walk(state, step, n);
+    match first {
+        None => states.swap_remove(n),
+        Some(prefix) => {
+            let period = states.len() - prefix;
+            if n < prefix {
+                return states.swap_remove(n);
+            }
+            states.swap_remove(prefix + (n - prefix) % period)
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // 3 -> 10 -> 5 -> 16 -> 8 -> 4 -> 2 -> 1 -> 4 -> ...
+    fn collatz(n: &u64) -> u64 {
+        if n.is_multiple_of(2) {
+            n / 2
+        } else {
+            3 * n + 1
+        }
+    }
+
+    #[test]
+    fn test_cycle_detection() {
+        assert_eq!(brent(&3, collatz), (5, 3));
+        assert_eq!(find_cycle(&3, collatz), (5, 3));
+        assert_eq!(brent(&4, collatz), (0, 3));
+        assert_eq!(find_cycle(&4, collatz), (0, 3));
+    }
+
+    #[test]
+    fn test_nth_state() {
+        assert_eq!(nth_state(3, collatz, 0), 3);
+        assert_eq!(nth_state(3, collatz, 4), 8);
+        assert_eq!(nth_state(3, collatz, 5), 4);
+        assert_eq!(nth_state(3, collatz, 7), 1);
+        assert_eq!(nth_state(3, collatz, 1_000_000_000), 1);
+    }
+}
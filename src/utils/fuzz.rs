@@ -0,0 +1,120 @@
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::utils::{parse::ParseError, rng::Rng};
+
+const CASES: usize = 2000;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// Fragments that tend to trip parsers up: separators, signs, numbers too big
+// for any integer type and characters wider than one byte.
+const FRAGMENTS: [&str; 14] = [
+    "\n",
+    "\n\n",
+    " ",
+    ",",
+    ":",
+    "-",
+    "=",
+    "#",
+    "0",
+    "99999999999999999999999",
+    "{",
+    "}",
+    "é",
+    "\r\n",
+];
+
+// Feeds the parser mangled copies of the seed inputs along with plain random
+// bytes, and fails on any input that makes it panic, run past the timeout or
+// blame a line that isn't there. Parsing happens on a worker thread so a hang
+// can be reported rather than stalling the test run.
+pub fn assert_parser_total<F>(seeds: &[&str], parse: F)
+where
+    F: Fn(&str) -> Result<(), ParseError> + Send + 'static,
+{
+    for seed in seeds {
+        assert_eq!(parse(seed), Ok(()), "seed doesn't parse: {:?}", seed);
+    }
+
+    let cases = Arc::new(fuzz_cases(seeds));
+    let (tx, rx) = mpsc::channel();
+
+    let worker_cases = Arc::clone(&cases);
+    thread::spawn(move || {
+        for case in worker_cases.iter() {
+            if tx.send(parse(case).err()).is_err() {
+                return;
+            }
+        }
+    });
+
+    for case in cases.iter() {
+        match rx.recv_timeout(TIMEOUT) {
+            Ok(Some(err)) => assert!(
+                err.line <= case.lines().count(),
+                "{} blames a missing line of {:?}",
+                err,
+                case
+            ),
+            Ok(None) => continue,
+            Err(mpsc::RecvTimeoutError::Timeout) => panic!("parser hung on {:?}", case),
+            Err(mpsc::RecvTimeoutError::Disconnected) => panic!("parser panicked on {:?}", case),
+        }
+    }
+}
+
+fn fuzz_cases(seeds: &[&str]) -> Vec<String> {
+    let mut rng = Rng::new(0);
+    let mut cases: Vec<String> = seeds.iter().map(|seed| seed.to_string()).collect();
+    cases.push(String::new());
+
+    while cases.len() < CASES {
+        let case = match rng.below(10) {
+            0 => {
+                let bytes: Vec<u8> = (0..rng.below(64)).map(|_| rng.below(256) as u8).collect();
+                String::from_utf8_lossy(&bytes).into_owned()
+            }
+            _ => {
+                let mut case = seeds[rng.below(seeds.len())].to_string();
+                for _ in 0..rng.range(1..5) {
+                    mutate(&mut rng, &mut case);
+                }
+                case
+            }
+        };
+        cases.push(case);
+    }
+
+    cases
+}
+
+// Applies one random edit, always cutting and splicing on char boundaries.
+fn mutate(rng: &mut Rng, case: &mut String) {
+    let boundaries: Vec<usize> = (0..=case.len())
+        .filter(|&i| case.is_char_boundary(i))
+        .collect();
+    let mut pick = || boundaries[rng.below(boundaries.len())];
+    let (a, b) = (pick(), pick());
+    let (start, end) = (a.min(b), a.max(b));
+
+    match rng.below(5) {
+        0 => case.replace_range(start..end, ""),
+        1 => case.insert_str(start, FRAGMENTS[rng.below(FRAGMENTS.len())]),
+        2 => {
+            let copy = case[start..end].to_string();
+            case.insert_str(end, &copy);
+        }
+        3 => case.truncate(start),
+        _ => {
+            let c = case[start..].chars().next();
+            let replacement = (b'!' + rng.below(94) as u8) as char;
+            if let Some(c) = c {
+                case.replace_range(start..start + c.len_utf8(), &replacement.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod cycle;
+#[cfg(test)]
+pub mod fuzz;
+pub mod parse;
+pub mod polygon;
+#[cfg(test)]
+pub mod property;
+pub mod rng;
@@ -0,0 +1,35 @@
+use std::{fmt, str::FromStr};
+
+// Why an input was rejected. Lines are numbered from 1, with 0 meaning the
+// input as a whole.
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl ParseError {
+    pub fn new(line: usize, reason: &'static str) -> ParseError {
+        ParseError { line, reason }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            0 => write!(f, "{}", self.reason),
+            line => write!(f, "line {}: {}", line, self.reason),
+        }
+    }
+}
+
+// Lines paired with their numbers, counting from 1.
+pub fn numbered_lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    input.lines().enumerate().map(|(i, line)| (i + 1, line))
+}
+
+// Parses a whole number of any type, rejecting anything that doesn't fit.
+pub fn number<T: FromStr>(line: usize, s: &str) -> Result<T, ParseError> {
+    s.parse()
+        .map_err(|_| ParseError::new(line, "expected a number"))
+}
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use num::integer::gcd;
+
+use crate::utils::rng::Rng;
+
+pub type Point = (i64, i64);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Location {
+    Inside,
+    Boundary,
+    Outside,
+}
+
+// The polygon is implicitly closed, so the last point may or may not repeat
+// the first one.
+fn edges(points: &[Point]) -> impl Iterator<Item = (Point, Point)> + '_ {
+    points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(&p1, &p2)| (p1, p2))
+}
+
+// Shoelace formula. Returns twice the signed area so that it stays an integer,
+// positive for counter-clockwise polygons.
+pub fn signed_area2(points: &[Point]) -> i64 {
+    edges(points)
+        .map(|(p1, p2)| p1.0 * p2.1 - p2.0 * p1.1)
+        .sum()
+}
+
+pub fn area(points: &[Point]) -> f64 {
+    signed_area2(points).abs() as f64 / 2.0
+}
+
+// Number of lattice points lying on the edges of the polygon.
+pub fn boundary_points(points: &[Point]) -> u64 {
+    edges(points)
+        .map(|(p1, p2)| gcd(p2.0 - p1.0, p2.1 - p1.1) as u64)
+        .sum()
+}
+
+// Number of lattice points strictly inside the polygon, from Pick's theorem:
+// A = i + b/2 - 1. None if the points don't make a simple polygon, which
+// shows up when a flat or self-overlapping one has more boundary than area.
+pub fn interior_points(points: &[Point]) -> Option<u64> {
+    let area2 = signed_area2(points).unsigned_abs();
+    let boundary = boundary_points(points);
+
+    Some((area2 + 2).checked_sub(boundary)? / 2)
+}
+
+fn on_segment(p: Point, p1: Point, p2: Point) -> bool {
+    let cross = (p2.0 - p1.0) * (p.1 - p1.1) - (p2.1 - p1.1) * (p.0 - p1.0);
+    cross == 0
+        && i64::min(p1.0, p2.0) <= p.0
+        && p.0 <= i64::max(p1.0, p2.0)
+        && i64::min(p1.1, p2.1) <= p.1
+        && p.1 <= i64::max(p1.1, p2.1)
+}
+
+// Crossing number test for any simple polygon. Each edge counts as crossing
+// the ray going in +x from p if it straddles p's row, half-open at the top so
+// vertices aren't counted twice.
+pub fn locate(points: &[Point], p: Point) -> Location {
+    let mut inside = false;
+    for (p1, p2) in edges(points) {
+        if on_segment(p, p1, p2) {
+            return Location::Boundary;
+        }
+        if (p1.1 > p.1) == (p2.1 > p.1) {
+            continue;
+        }
+
+        // Compare p.0 against the x of the edge at row p.1 without dividing.
+        let lhs = (p.0 - p1.0) * (p2.1 - p1.1);
+        let rhs = (p2.0 - p1.0) * (p.1 - p1.1);
+        let left_of_edge = if p2.1 > p1.1 { lhs < rhs } else { lhs > rhs };
+        if left_of_edge {
+            inside = !inside;
+        }
+    }
+
+    match inside {
+        true => Location::Inside,
+        false => Location::Outside,
+    }
+}
+
+// Same as locate, but only vertical edges can cross a horizontal ray so the
+// rest are skipped. Every edge must be axis-aligned.
+pub fn locate_rectilinear(points: &[Point], p: Point) -> Location {
+    let mut inside = false;
+    for (p1, p2) in edges(points) {
+        debug_assert!(p1.0 == p2.0 || p1.1 == p2.1);
+        if on_segment(p, p1, p2) {
+            return Location::Boundary;
+        }
+        if p1.0 != p2.0 || p1.0 < p.0 {
+            continue;
+        }
+        if (p1.1 > p.1) != (p2.1 > p.1) {
+            inside = !inside;
+        }
+    }
+
+    match inside {
+        true => Location::Inside,
+        false => Location::Outside,
+    }
+}
+
+// Bars of random widths adding up to `width`, each from 1 to `height` tall and
+// never as tall as the one before.
+fn random_bars(rng: &mut Rng, count: usize, width: i64, height: i64) -> Vec<(i64, i64)> {
+    // Floyd's sampling picks count - 1 distinct cuts from 1..width without
+    // building the whole range.
+    let mut picked = HashSet::new();
+    for j in width - count as i64 + 1..width {
+        let cut = rng.range(1..j + 1);
+        if !picked.insert(cut) {
+            picked.insert(j);
+        }
+    }
+    let mut cuts: Vec<i64> = picked.into_iter().collect();
+    cuts.extend([0, width]);
+    cuts.sort();
+
+    let mut bars: Vec<(i64, i64)> = Vec::new();
+    for pair in cuts.windows(2) {
+        let mut h = rng.range(1..height + 1);
+        while bars.last().is_some_and(|&(_, last)| last == h) {
+            h = rng.range(1..height + 1);
+        }
+        bars.push((pair[1] - pair[0], h));
+    }
+
+    bars
+}
+
+// Corners of a random simple polygon with axis-aligned edges: `count` bars
+// standing on the x axis and another `count` hanging below it, `width` wide in
+// total. Starts at the origin heading up the left side, and needs
+// width >= count, and height >= 2 unless count is 1.
+pub fn random_skyline(rng: &mut Rng, count: usize, width: i64, height: i64) -> Vec<Point> {
+    let mut corners = vec![(0, 0)];
+    let mut x = 0;
+    for (w, h) in random_bars(rng, count, width, height) {
+        corners.push((x, h));
+        x += w;
+        corners.push((x, h));
+    }
+    for (w, d) in random_bars(rng, count, width, height).into_iter().rev() {
+        corners.push((x, -d));
+        x -= w;
+        corners.push((x, -d));
+    }
+
+    corners
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    // An L shape with a notch cut out of the top right.
+    const SHAPE: [Point; 6] = [(0, 0), (4, 0), (4, 2), (2, 2), (2, 4), (0, 4)];
+
+    #[test]
+    fn test_polygon_counts() {
+        assert_eq!(signed_area2(&SHAPE), 24);
+        assert_eq!(area(&SHAPE), 12.0);
+        assert_eq!(boundary_points(&SHAPE), 16);
+        assert_eq!(interior_points(&SHAPE), Some(5));
+
+        let triangle = [(0, 0), (0, 3), (3, 0), (0, 0)];
+        assert_eq!(signed_area2(&triangle), -9);
+        assert_eq!(boundary_points(&triangle), 9);
+        assert_eq!(interior_points(&triangle), Some(1));
+
+        // A there-and-back line has boundary but no area.
+        let line = [(0, 0), (5, 0)];
+        assert_eq!(boundary_points(&line), 10);
+        assert_eq!(interior_points(&line), None);
+    }
+
+    #[test]
+    fn test_polygon_locate() {
+        let inside = [(1, 1), (2, 1), (3, 1), (1, 2), (1, 3)];
+        let mut boundary = 0;
+        for x in -1..=5 {
+            for y in -1..=5 {
+                let location = locate(&SHAPE, (x, y));
+                assert_eq!(locate_rectilinear(&SHAPE, (x, y)), location);
+                match location {
+                    Location::Inside => assert!(inside.contains(&(x, y))),
+                    Location::Boundary => boundary += 1,
+                    Location::Outside => assert!(!inside.contains(&(x, y))),
+                }
+            }
+        }
+        assert_eq!(boundary, 16);
+
+        let triangle = [(0, 0), (6, 0), (0, 6)];
+        assert_eq!(locate(&triangle, (1, 1)), Location::Inside);
+        assert_eq!(locate(&triangle, (3, 3)), Location::Boundary);
+        assert_eq!(locate(&triangle, (4, 3)), Location::Outside);
+    }
+
+    #[test]
+    fn test_polygon_random_bars() {
+        let mut rng = Rng::new(0);
+        for (count, width) in [(1, 1), (5, 5), (5, 6), (50, 1000), (1000, i64::MAX / 2)] {
+            let bars = random_bars(&mut rng, count, width, 3);
+            assert_eq!(bars.len(), count);
+            assert!(bars.iter().all(|&(w, h)| w > 0 && (1..=3).contains(&h)));
+            assert_eq!(bars.iter().map(|&(w, _)| w).sum::<i64>(), width);
+            assert!(bars.windows(2).all(|pair| pair[0].1 != pair[1].1));
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use std::fmt::Debug;
+
+use crate::utils::rng::Rng;
+
+// Runs both solvers on an input generated from each seed in 0..cases. A
+// failure names the seed and prints the input so it can be reproduced.
+pub fn assert_agree<T, G, F, R>(cases: u64, generate: G, fast: F, reference: R)
+where
+    T: Debug + PartialEq,
+    G: Fn(&mut Rng) -> String,
+    F: Fn(&str) -> T,
+    R: Fn(&str) -> T,
+{
+    for seed in 0..cases {
+        let input = generate(&mut Rng::new(seed));
+        assert_eq!(
+            fast(&input),
+            reference(&input),
+            "solvers disagree for seed {} on input:\n{}",
+            seed,
+            input
+        );
+    }
+}